@@ -0,0 +1,595 @@
+//! Just enough of a parser for Aseprite's `.ase`/`.aseprite` files
+//! to get the frames out as RGBA images, along with how long each
+//! one lasts and the animation tags.
+//!
+//! The format is documented at
+//! <https://github.com/aseprite/aseprite/blob/master/docs/ase-file-specs.md>.
+//! We flatten all the visible layers of each frame with normal blending;
+//! blend modes, tilemaps and such are ignored.
+
+use flate2::read::ZlibDecoder;
+
+use std::io::{self, Read};
+
+use error::Err;
+
+const FILE_MAGIC: u16 = 0xA5E0;
+const FRAME_MAGIC: u16 = 0xF1FA;
+
+const CHUNK_OLD_PALETTE: u16 = 0x0004;
+const CHUNK_LAYER: u16 = 0x2004;
+const CHUNK_CEL: u16 = 0x2005;
+const CHUNK_TAGS: u16 = 0x2018;
+const CHUNK_PALETTE: u16 = 0x2019;
+
+const CEL_RAW: u16 = 0;
+const CEL_LINKED: u16 = 1;
+const CEL_COMPRESSED: u16 = 2;
+
+const LAYER_VISIBLE: u16 = 1;
+const HEADER_LAYER_OPACITY_VALID: u32 = 1;
+
+/// Which way an animation tag plays.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Direction {
+    Forward,
+    Reverse,
+    PingPong,
+}
+
+/// A named range of frames, inclusive at both ends.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tag {
+    pub name: String,
+    pub from: usize,
+    pub to: usize,
+    pub direction: Direction,
+}
+
+#[derive(Clone, Debug)]
+pub struct Frame {
+    /// `width * height` pixels of RGBA, row by row.
+    pub rgba: Vec<u8>,
+    /// In seconds.
+    pub duration: f32,
+}
+
+/// A whole Aseprite file, flattened.
+#[derive(Clone, Debug)]
+pub struct Aseprite {
+    pub width: u16,
+    pub height: u16,
+    pub frames: Vec<Frame>,
+    pub tags: Vec<Tag>,
+}
+
+fn ase_error(msg: &str) -> Err {
+    Err::AsepriteError {
+        msg: msg.to_owned(),
+    }
+}
+
+/// Reads little-endian values out of a byte slice, failing
+/// politely rather than panicking if it runs out.
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Cursor { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], Err> {
+        if self.pos + n > self.data.len() {
+            return Err(ase_error("Unexpected end of file"));
+        }
+        let bytes = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, n: usize) -> Result<(), Err> {
+        self.bytes(n).map(|_| ())
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let bytes = &self.data[self.pos..];
+        self.pos = self.data.len();
+        bytes
+    }
+
+    fn u8(&mut self) -> Result<u8, Err> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Err> {
+        let b = self.bytes(2)?;
+        Ok(u16::from(b[0]) | (u16::from(b[1]) << 8))
+    }
+
+    fn i16(&mut self) -> Result<i16, Err> {
+        self.u16().map(|x| x as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32, Err> {
+        let b = self.bytes(4)?;
+        Ok(u32::from(b[0])
+            | (u32::from(b[1]) << 8)
+            | (u32::from(b[2]) << 16)
+            | (u32::from(b[3]) << 24))
+    }
+
+    fn string(&mut self) -> Result<String, Err> {
+        let len = self.u16()? as usize;
+        let bytes = self.bytes(len)?;
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Layer {
+    visible: bool,
+    opacity: u8,
+}
+
+/// A cel's pixels, already turned into RGBA.
+#[derive(Clone, Debug)]
+struct Cel {
+    layer: usize,
+    x: i32,
+    y: i32,
+    opacity: u8,
+    width: usize,
+    height: usize,
+    rgba: Vec<u8>,
+}
+
+/// What we know about the file while we're going through it.
+struct Parser {
+    width: usize,
+    height: usize,
+    depth: u16,
+    layer_opacity_valid: bool,
+    transparent_index: u8,
+    palette: Vec<[u8; 4]>,
+    layers: Vec<Layer>,
+    /// Visibility of each group we're inside of, by child level.
+    group_visibility: Vec<bool>,
+    /// Every frame's cels, so linked cels can find what they link to.
+    cels: Vec<Vec<Cel>>,
+    tags: Vec<Tag>,
+}
+
+impl Parser {
+    fn parse_layer(&mut self, c: &mut Cursor) -> Result<(), Err> {
+        let flags = c.u16()?;
+        let layer_type = c.u16()?;
+        let child_level = c.u16()? as usize;
+        c.skip(6)?; // default width and height, blend mode
+        let opacity = c.u8()?;
+
+        // A layer is only visible if all the groups it's in are too.
+        self.group_visibility.truncate(child_level);
+        let parents_visible = self.group_visibility.iter().all(|v| *v);
+        let visible = parents_visible && (flags & LAYER_VISIBLE != 0);
+        if layer_type == 1 {
+            // Groups have no pixels of their own, but they still
+            // take up a layer index.
+            while self.group_visibility.len() < child_level {
+                self.group_visibility.push(true);
+            }
+            self.group_visibility.push(visible);
+        }
+        self.layers.push(Layer {
+            visible: visible && layer_type != 1,
+            opacity: if self.layer_opacity_valid { opacity } else { 255 },
+        });
+        Ok(())
+    }
+
+    fn to_rgba(&self, pixels: &[u8], count: usize) -> Result<Vec<u8>, Err> {
+        let bytes_per_pixel = match self.depth {
+            32 => 4,
+            16 => 2,
+            8 => 1,
+            _ => return Err(ase_error("Unknown color depth")),
+        };
+        if pixels.len() < count * bytes_per_pixel {
+            return Err(ase_error("Cel has too few pixels"));
+        }
+        let mut rgba = Vec::with_capacity(count * 4);
+        for px in pixels.chunks(bytes_per_pixel).take(count) {
+            match self.depth {
+                32 => rgba.extend_from_slice(px),
+                16 => rgba.extend_from_slice(&[px[0], px[0], px[0], px[1]]),
+                _ => {
+                    if px[0] == self.transparent_index {
+                        rgba.extend_from_slice(&[0, 0, 0, 0]);
+                    } else {
+                        let color = self.palette.get(px[0] as usize).cloned();
+                        rgba.extend_from_slice(&color.unwrap_or([0, 0, 0, 0]));
+                    }
+                }
+            }
+        }
+        Ok(rgba)
+    }
+
+    fn parse_cel(&mut self, c: &mut Cursor, frame: usize) -> Result<Option<Cel>, Err> {
+        let layer = c.u16()? as usize;
+        let x = i32::from(c.i16()?);
+        let y = i32::from(c.i16()?);
+        let opacity = c.u8()?;
+        let cel_type = c.u16()?;
+        c.skip(7)?; // z-index and reserved
+        match cel_type {
+            CEL_RAW | CEL_COMPRESSED => {
+                let width = c.u16()? as usize;
+                let height = c.u16()? as usize;
+                let pixels = if cel_type == CEL_RAW {
+                    c.rest().to_owned()
+                } else {
+                    let mut pixels = Vec::new();
+                    ZlibDecoder::new(c.rest()).read_to_end(&mut pixels)?;
+                    pixels
+                };
+                let rgba = self.to_rgba(&pixels, width * height)?;
+                Ok(Some(Cel {
+                    layer,
+                    x,
+                    y,
+                    opacity,
+                    width,
+                    height,
+                    rgba,
+                }))
+            }
+            CEL_LINKED => {
+                let linked_frame = c.u16()? as usize;
+                if linked_frame >= frame {
+                    return Err(ase_error("Cel links to a frame that isn't before it"));
+                }
+                let linked = self.cels[linked_frame]
+                    .iter()
+                    .find(|cel| cel.layer == layer)
+                    .cloned();
+                // The link keeps the linked cel's pixels and position,
+                // but has its own opacity.
+                Ok(linked.map(|cel| Cel { opacity, ..cel }))
+            }
+            // Tilemaps and anything newer.
+            _ => Ok(None),
+        }
+    }
+
+    fn parse_palette(&mut self, c: &mut Cursor) -> Result<(), Err> {
+        let size = c.u32()? as usize;
+        let first = c.u32()? as usize;
+        let last = c.u32()? as usize;
+        c.skip(8)?;
+        if last < first || size > 65536 || last >= size.max(1) {
+            return Err(ase_error("Bad palette range"));
+        }
+        self.palette.resize(size, [0, 0, 0, 0]);
+        for i in first..=last {
+            let flags = c.u16()?;
+            let color = c.bytes(4)?;
+            self.palette[i] = [color[0], color[1], color[2], color[3]];
+            if flags & 1 != 0 {
+                c.string()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Older files only have this; newer ones have both, and
+    /// the new one wins since it comes first and we don't
+    /// overwrite it.
+    fn parse_old_palette(&mut self, c: &mut Cursor) -> Result<(), Err> {
+        if !self.palette.is_empty() {
+            return Ok(());
+        }
+        let packets = c.u16()?;
+        let mut index = 0;
+        for _ in 0..packets {
+            index += c.u8()? as usize;
+            let count = match c.u8()? {
+                0 => 256,
+                n => n as usize,
+            };
+            for _ in 0..count {
+                let color = c.bytes(3)?;
+                if self.palette.len() <= index {
+                    self.palette.resize(index + 1, [0, 0, 0, 0]);
+                }
+                self.palette[index] = [color[0], color[1], color[2], 255];
+                index += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_tags(&mut self, c: &mut Cursor) -> Result<(), Err> {
+        let count = c.u16()?;
+        c.skip(8)?;
+        for _ in 0..count {
+            let from = c.u16()? as usize;
+            let to = c.u16()? as usize;
+            let direction = match c.u8()? {
+                1 => Direction::Reverse,
+                2 | 3 => Direction::PingPong,
+                _ => Direction::Forward,
+            };
+            c.skip(12)?; // repeat count, reserved, color
+            let name = c.string()?;
+            self.tags.push(Tag {
+                name,
+                from,
+                to,
+                direction,
+            });
+        }
+        Ok(())
+    }
+
+    /// Flattens a frame's cels into a single image.
+    fn composite(&self, cels: &[Cel]) -> Vec<u8> {
+        let mut rgba = vec![0u8; self.width * self.height * 4];
+        let mut cels: Vec<&Cel> = cels.iter().collect();
+        cels.sort_by_key(|cel| cel.layer);
+        for cel in cels {
+            let layer = match self.layers.get(cel.layer) {
+                Some(layer) if layer.visible => layer,
+                _ => continue,
+            };
+            let opacity = f32::from(cel.opacity) / 255.0 * f32::from(layer.opacity) / 255.0;
+            for cy in 0..cel.height {
+                let y = cel.y + cy as i32;
+                if y < 0 || y >= self.height as i32 {
+                    continue;
+                }
+                for cx in 0..cel.width {
+                    let x = cel.x + cx as i32;
+                    if x < 0 || x >= self.width as i32 {
+                        continue;
+                    }
+                    let src = &cel.rgba[(cy * cel.width + cx) * 4..][..4];
+                    let dst = &mut rgba[(y as usize * self.width + x as usize) * 4..][..4];
+                    blend_over(dst, src, opacity);
+                }
+            }
+        }
+        rgba
+    }
+}
+
+/// Plain old "over" compositing of `src` on top of `dst`, both
+/// non-premultiplied RGBA.
+fn blend_over(dst: &mut [u8], src: &[u8], opacity: f32) {
+    let src_a = f32::from(src[3]) / 255.0 * opacity;
+    if src_a <= 0.0 {
+        return;
+    }
+    let dst_a = f32::from(dst[3]) / 255.0;
+    let out_a = src_a + dst_a * (1.0 - src_a);
+    for (d, s) in dst.iter_mut().zip(src).take(3) {
+        let s = f32::from(*s);
+        let d_value = f32::from(*d);
+        *d = ((s * src_a + d_value * dst_a * (1.0 - src_a)) / out_a).round() as u8;
+    }
+    dst[3] = (out_a * 255.0).round() as u8;
+}
+
+impl Aseprite {
+    pub fn from_reader<R: io::Read>(mut reader: R) -> Result<Self, Err> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Self::from_bytes(&data)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Err> {
+        let mut c = Cursor::new(data);
+        c.skip(4)?; // file size
+        if c.u16()? != FILE_MAGIC {
+            return Err(ase_error("Not an Aseprite file"));
+        }
+        let num_frames = c.u16()? as usize;
+        let width = c.u16()?;
+        let height = c.u16()?;
+        let depth = c.u16()?;
+        let flags = c.u32()?;
+        c.skip(10)?; // speed, and two reserved dwords
+        let transparent_index = c.u8()?;
+        let header_read = c.pos;
+        c.skip(128 - header_read)?;
+
+        let mut parser = Parser {
+            width: width as usize,
+            height: height as usize,
+            depth,
+            layer_opacity_valid: flags & HEADER_LAYER_OPACITY_VALID != 0,
+            transparent_index,
+            palette: Vec::new(),
+            layers: Vec::new(),
+            group_visibility: Vec::new(),
+            cels: Vec::new(),
+            tags: Vec::new(),
+        };
+        let mut frames = Vec::with_capacity(num_frames);
+        for frame in 0..num_frames {
+            let frame_size = c.u32()? as usize;
+            if frame_size < 16 {
+                return Err(ase_error("Frame is too small"));
+            }
+            let mut fc = Cursor::new(c.bytes(frame_size - 4)?);
+            if fc.u16()? != FRAME_MAGIC {
+                return Err(ase_error("Bad frame header"));
+            }
+            let old_chunks = fc.u16()? as usize;
+            let duration_ms = fc.u16()?;
+            fc.skip(2)?;
+            let num_chunks = match fc.u32()? as usize {
+                0 => old_chunks,
+                n => n,
+            };
+
+            let mut cels = Vec::new();
+            for _ in 0..num_chunks {
+                let chunk_size = fc.u32()? as usize;
+                if chunk_size < 6 {
+                    return Err(ase_error("Chunk is too small"));
+                }
+                let chunk_type = fc.u16()?;
+                let mut cc = Cursor::new(fc.bytes(chunk_size - 6)?);
+                match chunk_type {
+                    CHUNK_LAYER => parser.parse_layer(&mut cc)?,
+                    CHUNK_CEL => {
+                        if let Some(cel) = parser.parse_cel(&mut cc, frame)? {
+                            cels.push(cel);
+                        }
+                    }
+                    CHUNK_PALETTE => parser.parse_palette(&mut cc)?,
+                    CHUNK_OLD_PALETTE => parser.parse_old_palette(&mut cc)?,
+                    CHUNK_TAGS => parser.parse_tags(&mut cc)?,
+                    _ => (),
+                }
+            }
+            frames.push(Frame {
+                rgba: parser.composite(&cels),
+                duration: f32::from(duration_ms) / 1000.0,
+            });
+            parser.cels.push(cels);
+        }
+
+        for tag in &parser.tags {
+            if tag.from > tag.to || tag.to >= frames.len() {
+                return Err(ase_error(&format!("Tag {} has bad frames", tag.name)));
+            }
+        }
+
+        Ok(Aseprite {
+            width,
+            height,
+            frames,
+            tags: parser.tags,
+        })
+    }
+
+    /// The frame indices to play for the given tag, in order, or all
+    /// of them if there's no tag.  Returns `None` if there's no such tag.
+    pub fn tag_frames(&self, tag: Option<&str>) -> Option<(Vec<usize>, Direction)> {
+        match tag {
+            None => Some(((0..self.frames.len()).collect(), Direction::Forward)),
+            Some(name) => self.tags.iter().find(|t| t.name == name).map(|t| {
+                let mut indices: Vec<usize> = (t.from..=t.to).collect();
+                if t.direction == Direction::Reverse {
+                    indices.reverse();
+                }
+                (indices, t.direction)
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path;
+
+    fn image_path(file_name: &str) -> path::PathBuf {
+        path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join("images")
+            .join(file_name)
+    }
+
+    fn read_image(file_name: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        fs::File::open(image_path(file_name))
+            .and_then(|mut f| f.read_to_end(&mut data))
+            .expect("Could not read test image");
+        data
+    }
+
+    /// The width and height out of a PNG's header.
+    fn png_size(file_name: &str) -> (usize, usize) {
+        let data = read_image(file_name);
+        let be32 = |b: &[u8]| {
+            (usize::from(b[0]) << 24)
+                | (usize::from(b[1]) << 16)
+                | (usize::from(b[2]) << 8)
+                | usize::from(b[3])
+        };
+        (be32(&data[16..20]), be32(&data[20..24]))
+    }
+
+    #[test]
+    fn parses_astronaut() {
+        let ase = Aseprite::from_bytes(&read_image("astromonaut.ase")).unwrap();
+        let (width, height) = (ase.width as usize, ase.height as usize);
+        for i in 0..3 {
+            assert_eq!(png_size(&format!("astromonaut{}.png", i)), (width, height));
+        }
+        // It's a walk cycle of those three: 0, 1, 0, 2.  The strip
+        // version has them all side by side.
+        assert_eq!(ase.frames.len(), 4);
+        assert_eq!(png_size("astromonaut.png"), (width * 4, height));
+        for frame in &ase.frames {
+            assert_eq!(frame.rgba.len(), width * height * 4);
+            assert!((frame.duration - 0.1).abs() < 1e-6);
+            assert!(frame.rgba.chunks(4).any(|px| px[3] == 255), "Blank frame");
+        }
+        assert_eq!(ase.frames[0].rgba, ase.frames[2].rgba);
+        assert_ne!(ase.frames[0].rgba, ase.frames[1].rgba);
+        assert_ne!(ase.frames[1].rgba, ase.frames[3].rgba);
+        assert_eq!(
+            ase.tag_frames(None),
+            Some((vec![0, 1, 2, 3], Direction::Forward))
+        );
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let data = read_image("astromonaut.ase");
+        for len in 0..data.len() {
+            assert!(
+                Aseprite::from_bytes(&data[..len]).is_err(),
+                "File cut down to {} bytes still parsed",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn corrupted_files_are_errors() {
+        let data = read_image("astromonaut.ase");
+        let corruptions: [(usize, &[u8]); 6] = [
+            (4, &[0, 0]),               // file magic
+            (12, &[12, 0]),             // color depth
+            (128, &[8, 0, 0, 0]),       // first frame's size, too small
+            (128, &[0xFF, 0xFF, 0, 0]), // first frame's size, past the end
+            (128 + 4, &[0, 0]),         // first frame's magic
+            (128 + 16, &[3, 0, 0, 0]),  // first chunk's size, too small
+        ];
+        for &(offset, bytes) in &corruptions {
+            let mut bad = data.clone();
+            bad[offset..offset + bytes.len()].copy_from_slice(bytes);
+            assert!(
+                Aseprite::from_bytes(&bad).is_err(),
+                "Writing {:?} at byte {} still parsed",
+                bytes,
+                offset
+            );
+        }
+        // Anything else may or may not parse, but mustn't panic.
+        for offset in 0..data.len() {
+            let mut bad = data.clone();
+            bad[offset] ^= 0xFF;
+            let _ = Aseprite::from_bytes(&bad);
+        }
+    }
+}
@@ -0,0 +1,383 @@
+//! Sound effects and music.
+//!
+//! The game talks to an `Audio`, which keeps track of volumes and
+//! what music is playing, and hands the actual noise-making off to an
+//! `AudioBackend`.  `GgezAudio` plays things through ggez, `NullAudio`
+//! doesn't, so the game can run without an audio device, or a
+//! `ggez::Context` at all.
+
+use ggez::{self, audio};
+use warmy;
+
+use std::collections::HashMap;
+
+use events::GameEvent;
+use resources;
+
+/// Every sound effect there is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SoundEffect {
+    Jump,
+    Land,
+    Hit,
+    Victory,
+}
+
+/// Every sound effect there is, so we can load them all up front.
+pub const SOUND_EFFECTS: [SoundEffect; 4] = [
+    SoundEffect::Jump,
+    SoundEffect::Land,
+    SoundEffect::Hit,
+    SoundEffect::Victory,
+];
+
+impl SoundEffect {
+    /// The file it lives in.
+    pub fn key(self) -> &'static str {
+        match self {
+            SoundEffect::Jump => "/sounds/jump.wav",
+            SoundEffect::Land => "/sounds/land.wav",
+            SoundEffect::Hit => "/sounds/hit.wav",
+            SoundEffect::Victory => "/sounds/victory.wav",
+        }
+    }
+
+    /// What noise, if any, a game event makes.
+    pub fn for_event(event: GameEvent) -> Option<Self> {
+        match event {
+            GameEvent::Jumped => Some(SoundEffect::Jump),
+            GameEvent::Landed => Some(SoundEffect::Land),
+            GameEvent::ObstacleHit => Some(SoundEffect::Hit),
+        }
+    }
+}
+
+/// How loud things are, each from 0 to 1.  The music and sound
+/// effect volumes get multiplied by the master volume.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Volumes {
+    pub master: f32,
+    pub music: f32,
+    pub sfx: f32,
+}
+
+impl Default for Volumes {
+    fn default() -> Self {
+        Volumes {
+            master: 1.0,
+            music: 0.5,
+            sfx: 0.8,
+        }
+    }
+}
+
+impl Volumes {
+    fn music_volume(&self) -> f32 {
+        (self.master * self.music).max(0.0).min(1.0)
+    }
+
+    fn sfx_volume(&self) -> f32 {
+        (self.master * self.sfx).max(0.0).min(1.0)
+    }
+}
+
+/// Something that can actually make noise.
+pub trait AudioBackend {
+    fn play_sound(&mut self, effect: SoundEffect, volume: f32);
+    /// Starts the given music looping, replacing whatever was playing.
+    fn play_music(&mut self, key: &str, volume: f32);
+    fn set_music_volume(&mut self, volume: f32);
+    fn stop_music(&mut self);
+}
+
+/// Doesn't play anything.
+pub struct NullAudio;
+
+impl AudioBackend for NullAudio {
+    fn play_sound(&mut self, _effect: SoundEffect, _volume: f32) {}
+    fn play_music(&mut self, _key: &str, _volume: f32) {}
+    fn set_music_volume(&mut self, _volume: f32) {}
+    fn stop_music(&mut self) {}
+}
+
+/// Plays things through ggez.
+///
+/// ggez needs a `Context` to make a sound source, which scenes don't
+/// get in `update()`, so everything gets loaded up front.  Anything
+/// that's missing just doesn't play.
+pub struct GgezAudio {
+    sounds: HashMap<SoundEffect, audio::Source>,
+    music: HashMap<String, audio::Source>,
+    playing: Option<String>,
+}
+
+impl GgezAudio {
+    pub fn new<'a, I>(
+        store: &mut warmy::Store<ggez::Context>,
+        ctx: &mut ggez::Context,
+        music_keys: I,
+    ) -> Self
+    where
+        I: IntoIterator<Item = &'a String>,
+    {
+        let mut sounds = HashMap::new();
+        for effect in &SOUND_EFFECTS {
+            if let Some(source) = Self::load(store, ctx, effect.key()) {
+                sounds.insert(*effect, source);
+            }
+        }
+        let mut music = HashMap::new();
+        for key in music_keys {
+            if music.contains_key(key) {
+                continue;
+            }
+            if let Some(mut source) = Self::load(store, ctx, key) {
+                source.set_repeat(true);
+                music.insert(key.clone(), source);
+            }
+        }
+        GgezAudio {
+            sounds,
+            music,
+            playing: None,
+        }
+    }
+
+    fn load(
+        store: &mut warmy::Store<ggez::Context>,
+        ctx: &mut ggez::Context,
+        key: &str,
+    ) -> Option<audio::Source> {
+        let data = match store.get::<_, resources::SoundData>(&warmy::FSKey::new(key), ctx) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Could not load sound {}: {}", key, e);
+                return None;
+            }
+        };
+        let data = data.borrow().0.clone();
+        audio::Source::from_data(ctx, data)
+            .map_err(|e| warn!("Could not make sound {}: {}", key, e))
+            .ok()
+    }
+
+    fn current_music(&mut self) -> Option<&mut audio::Source> {
+        match self.playing {
+            Some(ref key) => self.music.get_mut(key),
+            None => None,
+        }
+    }
+}
+
+impl AudioBackend for GgezAudio {
+    fn play_sound(&mut self, effect: SoundEffect, volume: f32) {
+        if let Some(source) = self.sounds.get_mut(&effect) {
+            source.set_volume(volume);
+            if let Err(e) = source.play() {
+                warn!("Could not play sound {:?}: {}", effect, e);
+            }
+        }
+    }
+
+    fn play_music(&mut self, key: &str, volume: f32) {
+        self.stop_music();
+        match self.music.get_mut(key) {
+            Some(source) => {
+                source.set_volume(volume);
+                if let Err(e) = source.play() {
+                    warn!("Could not play music {}: {}", key, e);
+                }
+            }
+            None => {
+                warn!("Music {} wasn't loaded when the game started", key);
+                return;
+            }
+        }
+        self.playing = Some(key.to_owned());
+    }
+
+    fn set_music_volume(&mut self, volume: f32) {
+        if let Some(source) = self.current_music() {
+            source.set_volume(volume);
+        }
+    }
+
+    fn stop_music(&mut self) {
+        if let Some(source) = self.current_music() {
+            source.stop();
+        }
+        self.playing = None;
+    }
+}
+
+/// The game's interface to sound.
+pub struct Audio {
+    backend: Box<AudioBackend>,
+    volumes: Volumes,
+    music: Option<String>,
+}
+
+impl Audio {
+    pub fn new(backend: Box<AudioBackend>) -> Self {
+        Audio {
+            backend,
+            volumes: Volumes::default(),
+            music: None,
+        }
+    }
+
+    /// An `Audio` that never makes a sound.
+    pub fn null() -> Self {
+        Self::new(Box::new(NullAudio))
+    }
+
+    pub fn set_volumes(&mut self, volumes: Volumes) {
+        self.volumes = volumes;
+        self.backend.set_music_volume(volumes.music_volume());
+    }
+
+    pub fn play_sound(&mut self, effect: SoundEffect) {
+        let volume = self.volumes.sfx_volume();
+        if volume > 0.0 {
+            self.backend.play_sound(effect, volume);
+        }
+    }
+
+    /// Plays whatever sound goes with a game event.
+    pub fn handle_event(&mut self, event: GameEvent) {
+        if let Some(effect) = SoundEffect::for_event(event) {
+            self.play_sound(effect);
+        }
+    }
+
+    /// Starts some music looping, or stops it if there's `None`.
+    /// If it's already playing it just keeps going.
+    pub fn play_music(&mut self, key: Option<&str>) {
+        if self.music.as_ref().map(|s| s.as_str()) == key {
+            return;
+        }
+        match key {
+            Some(key) => self.backend.play_music(key, self.volumes.music_volume()),
+            None => self.backend.stop_music(),
+        }
+        self.music = key.map(|k| k.to_owned());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Something an `AudioBackend` got asked to do.
+    #[derive(Clone, Debug, PartialEq)]
+    enum Call {
+        Sound(SoundEffect, f32),
+        Music(String, f32),
+        MusicVolume(f32),
+        StopMusic,
+    }
+
+    /// Writes down everything it's asked to do, somewhere the test
+    /// can still see once the `Audio` owns it.
+    struct RecordingAudio(Rc<RefCell<Vec<Call>>>);
+
+    impl AudioBackend for RecordingAudio {
+        fn play_sound(&mut self, effect: SoundEffect, volume: f32) {
+            self.0.borrow_mut().push(Call::Sound(effect, volume));
+        }
+        fn play_music(&mut self, key: &str, volume: f32) {
+            self.0.borrow_mut().push(Call::Music(key.to_owned(), volume));
+        }
+        fn set_music_volume(&mut self, volume: f32) {
+            self.0.borrow_mut().push(Call::MusicVolume(volume));
+        }
+        fn stop_music(&mut self) {
+            self.0.borrow_mut().push(Call::StopMusic);
+        }
+    }
+
+    fn recording_audio() -> (Audio, Rc<RefCell<Vec<Call>>>) {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let audio = Audio::new(Box::new(RecordingAudio(calls.clone())));
+        (audio, calls)
+    }
+
+    /// Returns the calls made since last time.
+    fn take(calls: &Rc<RefCell<Vec<Call>>>) -> Vec<Call> {
+        calls.borrow_mut().drain(..).collect()
+    }
+
+    #[test]
+    fn events_make_the_right_sounds() {
+        let (mut audio, calls) = recording_audio();
+        let sfx = Volumes::default().sfx_volume();
+        let expected = [
+            (GameEvent::Jumped, SoundEffect::Jump),
+            (GameEvent::Landed, SoundEffect::Land),
+            (GameEvent::ObstacleHit, SoundEffect::Hit),
+        ];
+        for &(event, effect) in &expected {
+            audio.handle_event(event);
+            assert_eq!(take(&calls), vec![Call::Sound(effect, sfx)], "{:?}", event);
+        }
+    }
+
+    #[test]
+    fn music_only_changes_when_asked_for_something_else() {
+        let (mut audio, calls) = recording_audio();
+        let music = Volumes::default().music_volume();
+
+        audio.play_music(Some("/music/a.ogg"));
+        assert_eq!(take(&calls), vec![Call::Music("/music/a.ogg".into(), music)]);
+        audio.play_music(Some("/music/a.ogg"));
+        assert_eq!(take(&calls), vec![]);
+
+        audio.play_music(Some("/music/b.ogg"));
+        assert_eq!(take(&calls), vec![Call::Music("/music/b.ogg".into(), music)]);
+
+        audio.play_music(None);
+        assert_eq!(take(&calls), vec![Call::StopMusic]);
+        audio.play_music(None);
+        assert_eq!(take(&calls), vec![]);
+    }
+
+    #[test]
+    fn volumes_get_scaled_by_master() {
+        let (mut audio, calls) = recording_audio();
+        audio.set_volumes(Volumes {
+            master: 0.5,
+            music: 0.5,
+            sfx: 1.0,
+        });
+        assert_eq!(take(&calls), vec![Call::MusicVolume(0.25)]);
+        audio.play_sound(SoundEffect::Jump);
+        assert_eq!(take(&calls), vec![Call::Sound(SoundEffect::Jump, 0.5)]);
+        audio.play_music(Some("/music/a.ogg"));
+        assert_eq!(take(&calls), vec![Call::Music("/music/a.ogg".into(), 0.25)]);
+    }
+
+    #[test]
+    fn zero_volume_is_silent() {
+        let (mut audio, calls) = recording_audio();
+        audio.set_volumes(Volumes {
+            master: 0.0,
+            music: 1.0,
+            sfx: 1.0,
+        });
+        assert_eq!(take(&calls), vec![Call::MusicVolume(0.0)]);
+        audio.play_sound(SoundEffect::Hit);
+        assert_eq!(take(&calls), vec![]);
+
+        audio.set_volumes(Volumes {
+            master: 1.0,
+            music: 1.0,
+            sfx: 0.0,
+        });
+        take(&calls);
+        audio.handle_event(GameEvent::Landed);
+        assert_eq!(take(&calls), vec![]);
+    }
+}
@@ -0,0 +1,233 @@
+//! The campaign: the ordered list of levels the player works
+//! their way through, and which of them they've unlocked.
+//!
+//! How far they've got gets saved in the save dir, so it's still
+//! unlocked next time they play.
+
+use ggez;
+use ron;
+use warmy;
+
+use std::path;
+
+use error::Err;
+use resources;
+use util;
+
+/// What the campaign file (`/levels/campaign.ron`) looks like.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CampaignDef {
+    /// Paths of the level files, in the order they get played.
+    levels: Vec<String>,
+}
+
+/// What the progress file (`progress.ron` in the save dir) looks like.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Progress {
+    unlocked: usize,
+    current: usize,
+}
+
+pub struct Campaign {
+    levels: Vec<warmy::Res<resources::Level>>,
+    /// How many levels, counting from the first one, are playable.
+    unlocked: usize,
+    /// The level being played, or the last one that was.
+    current: usize,
+}
+
+impl Default for Campaign {
+    /// An empty campaign, for when there's no `Context` to load one with.
+    fn default() -> Self {
+        Self {
+            levels: Vec::new(),
+            unlocked: 1,
+            current: 0,
+        }
+    }
+}
+
+impl Campaign {
+    const PROGRESS_FILE: &'static str = "progress.ron";
+
+    /// Loads the campaign file and all the levels in it.
+    pub fn load(
+        ctx: &mut ggez::Context,
+        store: &mut warmy::Store<ggez::Context>,
+        path: &str,
+    ) -> Result<Self, Err> {
+        let file = ctx.filesystem.open(path)?;
+        let def: CampaignDef = ron::de::from_reader(file)?;
+        let mut levels = Vec::new();
+        for level_path in &def.levels {
+            let level = store
+                .get::<_, resources::Level>(&warmy::FSKey::new(level_path), ctx)
+                .map_err(|e| Err::LevelError {
+                    msg: format!("Could not load level {}: {:?}", level_path, e),
+                })?;
+            levels.push(level);
+        }
+        if levels.is_empty() {
+            return Err(Err::LevelError {
+                msg: format!("Campaign {} has no levels in it", path),
+            });
+        }
+        Ok(Self {
+            levels,
+            ..Self::default()
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn level(&self, index: usize) -> Option<warmy::Res<resources::Level>> {
+        self.levels.get(index).cloned()
+    }
+
+    /// The name of each level, in order.
+    pub fn level_names(&self) -> Vec<String> {
+        self.levels.iter().map(|l| l.borrow().0.name.clone()).collect()
+    }
+
+    /// The music for every level that has some, so it can all be
+    /// loaded up front.
+    pub fn music(&self) -> Vec<String> {
+        self.levels
+            .iter()
+            .filter_map(|l| l.borrow().0.music.clone())
+            .collect()
+    }
+
+    pub fn is_unlocked(&self, index: usize) -> bool {
+        index < self.unlocked && index < self.levels.len()
+    }
+
+    /// Makes the given level the current one, if it's unlocked,
+    /// and returns it.
+    pub fn select(&mut self, index: usize) -> Option<warmy::Res<resources::Level>> {
+        if !self.is_unlocked(index) {
+            return None;
+        }
+        self.current = index;
+        self.level(index)
+    }
+
+    /// Marks the current level as won, unlocking the next one.
+    /// Returns the index of the next level, if there is one.
+    pub fn complete_current(&mut self) -> Option<usize> {
+        let next = self.current + 1;
+        if next < self.levels.len() {
+            self.unlocked = self.unlocked.max(next + 1);
+            Some(next)
+        } else {
+            None
+        }
+    }
+
+    /// Picks up where the player left off, if they've played before.
+    /// Anything out of range for the levels we actually have gets
+    /// clamped, in case the campaign's changed since.
+    pub fn load_progress(&mut self, dir: &path::Path) -> Result<(), Err> {
+        let path = dir.join(Self::PROGRESS_FILE);
+        if !path.exists() {
+            return Ok(());
+        }
+        let progress: Progress = util::load_ron(&path)?;
+        self.unlocked = progress.unlocked.max(1).min(self.levels.len().max(1));
+        self.current = progress.current.min(self.unlocked - 1);
+        Ok(())
+    }
+
+    pub fn save_progress(&self, dir: &path::Path) -> Result<(), Err> {
+        let progress = Progress {
+            unlocked: self.unlocked,
+            current: self.current,
+        };
+        util::save_ron(&dir.join(Self::PROGRESS_FILE), &progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use level::LevelDef;
+    use std::fs;
+
+    /// A campaign of `len` copies of the first level, since loading the
+    /// real one needs a `Context`.
+    fn campaign_of(len: usize) -> Campaign {
+        let level_path = path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join("levels")
+            .join("level1.ron");
+        let file = fs::File::open(&level_path).expect("Could not open level file");
+        let def = LevelDef::from_reader(file).expect("Could not parse level file");
+        Campaign {
+            levels: (0..len)
+                .map(|_| warmy::Res::new(resources::Level(def.clone())))
+                .collect(),
+            ..Campaign::default()
+        }
+    }
+
+    #[test]
+    fn progress_round_trips() {
+        let dir = util::test_dir("campaign-round-trip");
+        let mut campaign = campaign_of(3);
+        assert_eq!(campaign.complete_current(), Some(1));
+        assert!(campaign.select(1).is_some());
+        assert!(!campaign.is_unlocked(2));
+        campaign.save_progress(&dir).unwrap();
+
+        let mut loaded = campaign_of(3);
+        loaded.load_progress(&dir).unwrap();
+        assert_eq!(loaded.current(), 1);
+        assert!(loaded.is_unlocked(1));
+        assert!(!loaded.is_unlocked(2));
+    }
+
+    #[test]
+    fn out_of_range_progress_gets_clamped() {
+        let dir = util::test_dir("campaign-clamp");
+        let progress = Progress {
+            unlocked: 10,
+            current: 9,
+        };
+        util::save_ron(&dir.join(Campaign::PROGRESS_FILE), &progress).unwrap();
+
+        let mut campaign = campaign_of(2);
+        campaign.load_progress(&dir).unwrap();
+        assert_eq!(campaign.current(), 1);
+        assert!(campaign.is_unlocked(1));
+        assert!(campaign.select(1).is_some());
+        assert_eq!(campaign.complete_current(), None);
+    }
+
+    #[test]
+    fn missing_progress_starts_from_the_beginning() {
+        let dir = util::test_dir("campaign-missing");
+        let mut campaign = campaign_of(2);
+        campaign.load_progress(&dir).unwrap();
+        assert_eq!(campaign.current(), 0);
+        assert!(campaign.is_unlocked(0));
+        assert!(!campaign.is_unlocked(1));
+    }
+
+    #[test]
+    fn garbage_progress_is_an_error() {
+        let dir = util::test_dir("campaign-garbage");
+        fs::write(dir.join(Campaign::PROGRESS_FILE), "this is not ron {{{").unwrap();
+        let mut campaign = campaign_of(2);
+        assert!(campaign.load_progress(&dir).is_err());
+        // And it leaves things how they were.
+        assert_eq!(campaign.current(), 0);
+        assert!(!campaign.is_unlocked(1));
+    }
+}
@@ -6,19 +6,63 @@ use util::*;
 /// ///////////////////////////////////////////////////////////////////////
 /// Components
 /// ///////////////////////////////////////////////////////////////////////
+
+/// Velocity in pixels/s, acceleration in pixels/s^2.
 #[derive(Clone, Debug, Component)]
 #[storage(VecStorage)]
 pub struct Motion {
     pub velocity: Vector2,
     pub acceleration: Vector2,
+    /// Last tick's acceleration, for Verlet integration.
+    /// `None` until the entity has moved once.
+    pub last_acceleration: Option<Vector2>,
+}
+
+impl Motion {
+    pub fn new(velocity: Vector2) -> Self {
+        Self {
+            velocity,
+            acceleration: Vector2::new(0.0, 0.0),
+            last_acceleration: None,
+        }
+    }
 }
 
 /// Objects without one won't get affected by the `Gravity` system.
-#[derive(Clone, Debug, Component)]
+#[derive(Clone, Debug, Default, Component)]
 #[storage(VecStorage)]
-pub struct Mass {}
+pub struct Mass {
+    /// Whichever `Gravity` source pulled on this hardest last tick.
+    /// For the player, that's where "down" is.
+    pub dominant_source: Option<Entity>,
+}
+
+/// How much say the player has over how they run.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControlMode {
+    /// The player always speeds up and can only jump.
+    AutoRun,
+    /// Forward and back speed up and brake, and down falls faster
+    /// while in the air.
+    Steered,
+}
+
+impl Default for ControlMode {
+    fn default() -> Self {
+        ControlMode::AutoRun
+    }
+}
 
 /// Just a marker that a particular entity is the player.
+///
+/// All the tuning values are in seconds; `jump_force` is the
+/// velocity the player leaves the ground with, `velocity` grows
+/// by `run_acceleration` per second and pushes the player forward
+/// at `velocity * run_force` pixels/s^2.
+///
+/// With `ControlMode::Steered`, `velocity` only grows while going
+/// forward is held, and shrinks `BRAKE_FACTOR` times as fast while
+/// going back is; it can go negative, which pushes backwards.
 #[derive(Clone, Debug, Component)]
 #[storage(HashMapStorage)]
 pub struct Player {
@@ -27,10 +71,42 @@ pub struct Player {
     pub jump_force: f32,
     pub velocity: f32,
     pub run_acceleration: f32,
+    pub run_force: f32,
     pub tumbling_timer: f32,
     pub friction: f32,
+    pub control_mode: ControlMode,
+    /// Which steering inputs are held down.
+    pub forward: bool,
+    pub back: bool,
+    pub fast_falling: bool,
 }
 
+impl Player {
+    /// How hard the player's trying to speed up, from -1 to 1.
+    pub fn run_input(&self) -> f32 {
+        match self.control_mode {
+            ControlMode::AutoRun => 1.0,
+            ControlMode::Steered => match (self.forward, self.back) {
+                (true, false) => 1.0,
+                (false, true) => -BRAKE_FACTOR,
+                _ => 0.0,
+            },
+        }
+    }
+}
+
+/// How much faster braking is than speeding up, in `ControlMode::Steered`.
+pub const BRAKE_FACTOR: f32 = 3.0;
+/// How hard fast-falling pulls the player down, in pixels/s^2.
+pub const FAST_FALL_ACCELERATION: f32 = 600.0;
+
+/// How long the player tumbles for after hitting something, in seconds.
+pub const TUMBLE_TIME: f32 = 0.8;
+/// How fast the player spins while tumbling, in radians per second.
+pub const TUMBLE_SPIN: f32 = 6.0;
+/// `Player::friction` while tumbling; the fraction of velocity lost per second.
+pub const TUMBLE_FRICTION: f32 = 6.0;
+
 /// NCollide collision object handle.
 /// This also stores position and orientation info.
 #[derive(Clone, Debug, Component)]
@@ -39,22 +115,178 @@ pub struct Collider {
     pub object_handle: nc::world::CollisionObjectHandle,
 }
 
-/// Sprite marker.
-/// Should someday say something about what sprite to draw.
+/// A non-colliding copy of the player replaying an old run.
+/// Nothing simulates it, whoever owns it just moves it around.
+#[derive(Clone, Debug, Component)]
+#[storage(HashMapStorage)]
+pub struct Ghost {
+    pub position: Point2,
+    pub rotation: f32,
+}
+
+/// An image to draw for an entity, at its `Collider` or `Ghost` position.
+///
+/// The image is the warmy key of a `resources::Image`, or of a
+/// `resources::SpriteSheet` if it's an Aseprite file, in which case
+/// `frame` says which frame of it to draw.  It's a key rather than a
+/// `warmy::Res` since those can't be shared between threads; whatever
+/// draws it looks it up in the asset store.
 #[derive(Clone, Debug, Component)]
 #[storage(VecStorage)]
 pub struct Sprite {
-    //image: warmy::Res<resources::Image>,
+    pub image: String,
+    pub frame: usize,
+    /// Extra rotation on top of the entity's own, in radians.
+    pub rotation: f32,
+    pub alpha: f32,
+}
+
+impl Sprite {
+    pub fn new(image: &str) -> Self {
+        Self {
+            image: image.to_owned(),
+            frame: 0,
+            rotation: 0.0,
+            alpha: 1.0,
+        }
+    }
 }
 
-/// Mesh
+/// Whether the given warmy key is an Aseprite file.
+pub fn is_sprite_sheet(key: &str) -> bool {
+    key.ends_with(".ase") || key.ends_with(".aseprite")
+}
+
+/// What an `Animation` does once it gets to the last frame.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LoopMode {
+    /// Start over from the first frame.
+    Loop,
+    /// Stay on the last frame.
+    Once,
+    /// Go backwards to the first frame, then forwards again, and so on.
+    PingPong,
+}
+
+/// One frame of an `Animation`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationFrame {
+    /// warmy key of the image or sprite sheet.
+    pub image: String,
+    /// Which frame of the sprite sheet, if it is one.
+    pub index: usize,
+    /// How long the frame lasts, in seconds.
+    pub duration: f32,
+}
+
+/// Flips through images for a `Sprite`; `AnimationSystem` points the
+/// sprite at whichever frame we're on.
+///
+/// If `sheet` is set, the frames come from an Aseprite file instead,
+/// and get filled in (and refreshed when the file changes) by whatever
+/// has the asset store, since the durations live in the file.
+#[derive(Clone, Debug, Component)]
+#[storage(HashMapStorage)]
+pub struct Animation {
+    pub frames: Vec<AnimationFrame>,
+    pub mode: LoopMode,
+    /// The Aseprite file and optional tag in it to animate.
+    pub sheet: Option<(String, Option<String>)>,
+    /// Which frame we're on.
+    pub current: usize,
+    /// How long we've been on it.
+    pub timer: f32,
+    /// Only used for `LoopMode::PingPong`.
+    pub reversed: bool,
+}
+
+impl Animation {
+    /// Animates the frames of an Aseprite file, or just the ones in
+    /// the given tag.
+    pub fn from_sheet(key: &str, tag: Option<&str>, mode: LoopMode) -> Self {
+        Self {
+            frames: Vec::new(),
+            mode,
+            sheet: Some((key.to_owned(), tag.map(|t| t.to_owned()))),
+            current: 0,
+            timer: 0.0,
+            reversed: false,
+        }
+    }
+
+    /// Replaces the frames, if they've changed, starting over
+    /// if we'd end up past the end.
+    pub fn set_frames(&mut self, frames: Vec<AnimationFrame>) {
+        if frames != self.frames {
+            if self.current >= frames.len() {
+                self.current = 0;
+                self.timer = 0.0;
+            }
+            self.frames = frames;
+        }
+    }
+
+    /// Moves on to the next frame, however `mode` says to.
+    pub fn advance(&mut self) {
+        let last = self.frames.len().saturating_sub(1);
+        match self.mode {
+            LoopMode::Loop => {
+                self.current = if self.current >= last {
+                    0
+                } else {
+                    self.current + 1
+                };
+            }
+            LoopMode::Once => self.current = (self.current + 1).min(last),
+            LoopMode::PingPong => {
+                if last == 0 {
+                    return;
+                }
+                if self.current == last {
+                    self.reversed = true;
+                } else if self.current == 0 {
+                    self.reversed = false;
+                }
+                if self.reversed {
+                    self.current -= 1;
+                } else {
+                    self.current += 1;
+                }
+            }
+        }
+    }
+
+    pub fn current_frame(&self) -> Option<&AnimationFrame> {
+        self.frames.get(self.current)
+    }
+}
+
+/// What shape a `Mesh` is, so we can build the actual
+/// `ggez::graphics::Mesh` later on when we have a `Context`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MeshShape {
+    Circle { radius: f32 },
+    Square { halfwidth: f32 },
+}
+
+/// Mesh.  `mesh` starts out as `None` and gets built
+/// from `shape` the first time something draws it, so
+/// a headless simulation never needs a `Context`.
 #[derive(Clone, Debug, Component)]
 #[storage(VecStorage)]
 pub struct Mesh {
-    pub mesh: ggez::graphics::Mesh,
+    pub shape: MeshShape,
+    pub mesh: Option<ggez::graphics::Mesh>,
+}
+
+impl Mesh {
+    pub fn new(shape: MeshShape) -> Self {
+        Self { shape, mesh: None }
+    }
 }
 
 /// Gravity force; needs to go along with a Collider component.
+/// Something `d` pixels away gets accelerated at `force / d` pixels/s^2.
 #[derive(Clone, Debug, Component)]
 #[storage(HashMapStorage)]
 pub struct Gravity {
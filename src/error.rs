@@ -1,8 +1,11 @@
 //! Basic error types.
 
 use ggez;
+use ron;
 use specs;
 
+use std::io;
+
 #[derive(Debug, Fail)]
 pub enum Err {
     #[fail(display = "ggez error: {:?}", err)]
@@ -10,6 +13,27 @@ pub enum Err {
 
     #[fail(display = "specs error: {:?}", err)]
     SpecsError { err: specs::error::Error },
+
+    #[fail(display = "IO error: {:?}", err)]
+    IoError { err: io::Error },
+
+    #[fail(display = "RON parse error: {:?}", err)]
+    RonError { err: ron::de::Error },
+
+    #[fail(display = "RON serialization error: {:?}", err)]
+    RonSerError { err: ron::ser::Error },
+
+    #[fail(display = "Invalid level: {}", msg)]
+    LevelError { msg: String },
+
+    #[fail(display = "Invalid key bindings: {}", msg)]
+    BindingError { msg: String },
+
+    #[fail(display = "Invalid save file: {}", msg)]
+    SaveError { msg: String },
+
+    #[fail(display = "Could not read Aseprite file: {}", msg)]
+    AsepriteError { msg: String },
 }
 
 impl From<ggez::GameError> for Err {
@@ -23,3 +47,21 @@ impl From<specs::error::Error> for Err {
         Err::SpecsError { err }
     }
 }
+
+impl From<io::Error> for Err {
+    fn from(err: io::Error) -> Self {
+        Err::IoError { err }
+    }
+}
+
+impl From<ron::de::Error> for Err {
+    fn from(err: ron::de::Error) -> Self {
+        Err::RonError { err }
+    }
+}
+
+impl From<ron::ser::Error> for Err {
+    fn from(err: ron::ser::Error) -> Self {
+        Err::RonSerError { err }
+    }
+}
@@ -0,0 +1,52 @@
+//! Events that systems send each other through `shrev::EventChannel`s.
+//!
+//! Each `Simulation` puts fresh channels into the specs world when it
+//! starts, so nothing from an old level leaks into a new one.
+
+use ncollide2d as nc;
+use specs;
+
+/// Two entities that have started or stopped touching, along with
+/// what collision groups they're in so reactions can tell what
+/// sort of thing hit what.
+#[derive(Copy, Clone, Debug)]
+pub struct Contact {
+    pub a: specs::Entity,
+    pub b: specs::Entity,
+    pub a_groups: nc::world::CollisionGroups,
+    pub b_groups: nc::world::CollisionGroups,
+}
+
+impl Contact {
+    /// The same contact with `a` and `b` swapped.
+    pub fn flipped(&self) -> Self {
+        Contact {
+            a: self.b,
+            b: self.a,
+            a_groups: self.b_groups,
+            b_groups: self.a_groups,
+        }
+    }
+
+    /// Both ways round, so a reaction only has to check for
+    /// "a is the thing I care about".
+    pub fn both_ways(&self) -> [Contact; 2] {
+        [*self, self.flipped()]
+    }
+}
+
+/// Things happening in the game that something outside the simulation,
+/// like the audio, might want to react to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameEvent {
+    Jumped,
+    Landed,
+    ObstacleHit,
+}
+
+/// Sent by `CollisionEventSystem` for every ncollide contact event.
+#[derive(Copy, Clone, Debug)]
+pub enum CollisionEvent {
+    Started(Contact),
+    Stopped(Contact),
+}
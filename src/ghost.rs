@@ -0,0 +1,108 @@
+//! Saving the player's best run through a level, so we can
+//! show it as a ghost to race against.
+//!
+//! Unlike a `Replay` this just stores where the player was on
+//! every tick, so it doesn't matter if the level or the physics
+//! change out from under it; the ghost will just be wrong.
+
+use std::path;
+
+use error::Err;
+use util::*;
+
+/// Where the player was and which way they were facing on one tick.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GhostFrame {
+    pub x: f32,
+    pub y: f32,
+    pub rotation: f32,
+}
+
+/// One frame per tick of a run, from start to reaching orbit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trajectory {
+    /// The name of the level, as in `LevelDef::name`.
+    pub level: String,
+    /// Where the player was after each step, so `frames[0]` is
+    /// after tick 1.
+    pub frames: Vec<GhostFrame>,
+}
+
+impl Trajectory {
+    pub fn new(level: &str) -> Self {
+        Self {
+            level: level.to_owned(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, position: Point2, rotation: f32) {
+        self.frames.push(GhostFrame {
+            x: position.x,
+            y: position.y,
+            rotation,
+        });
+    }
+
+    /// Returns the frame for once the given number of ticks have
+    /// run.  Before the first tick that's just the first frame, and
+    /// once the run is over the ghost hangs around where it finished.
+    pub fn frame(&self, tick: u64) -> Option<GhostFrame> {
+        let idx = (tick.saturating_sub(1) as usize).min(self.frames.len().saturating_sub(1));
+        self.frames.get(idx).cloned()
+    }
+
+    fn path_in(dir: &path::Path, level: &str) -> path::PathBuf {
+        dir.join(format!("ghost-{}.ron", file_safe_name(level)))
+    }
+
+    /// Loads the best run for the given level, if there is one.
+    pub fn load_best(dir: &path::Path, level: &str) -> Result<Option<Self>, Err> {
+        let path = Self::path_in(dir, level);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let trajectory: Self = load_ron(&path)?;
+        if trajectory.frames.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(trajectory))
+    }
+
+    /// Saves this run if it's faster than the best one saved so far.
+    /// Returns whether it was.
+    pub fn save_if_best(&self, dir: &path::Path) -> Result<bool, Err> {
+        // A broken save file shouldn't stop us from saving a new one.
+        let best = Self::load_best(dir, &self.level).unwrap_or_else(|e| {
+            warn!("Could not load old ghost for {}: {}", self.level, e);
+            None
+        });
+        if let Some(best) = best {
+            if best.frames.len() <= self.frames.len() {
+                return Ok(false);
+            }
+        }
+        save_ron(&Self::path_in(dir, &self.level), self)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_line_up_with_ticks() {
+        let mut trajectory = Trajectory::new("test");
+        for i in 0..3 {
+            trajectory.push(Point2::new(i as f32, 0.0), 0.0);
+        }
+        let frames = trajectory.frames.clone();
+        assert_eq!(trajectory.frame(0), Some(frames[0]));
+        assert_eq!(trajectory.frame(1), Some(frames[0]));
+        assert_eq!(trajectory.frame(2), Some(frames[1]));
+        assert_eq!(trajectory.frame(3), Some(frames[2]));
+        assert_eq!(trajectory.frame(100), Some(frames[2]));
+        assert_eq!(Trajectory::new("empty").frame(1), None);
+    }
+}
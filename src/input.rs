@@ -1,30 +1,423 @@
-//! Typedefs for input shortcuts.
-use ggez::event::*;
-use ggez_goodies::input;
+//! Typedefs for input shortcuts, the player's key bindings, and
+//! turning gamepad buttons and sticks into the same input events
+//! the keyboard makes.
+use ggez::event::{Axis as PadAxis, Button as PadButton, *};
+use ggez_goodies::input::{self, InputEffect};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+use std::collections::{BTreeMap, HashMap};
+
+use error::Err;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Button {
     Jump,
     Menu,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Axis {
     Vert,
     Horz,
 }
 
-pub type InputBinding = input::InputBinding<Axis, Button>;
 pub type InputEvent = input::InputEffect<Axis, Button>;
 pub type InputState = input::InputState<Axis, Button>;
 
+/// How far a stick has to be pushed, out of 1, before it counts.
+pub const DEADZONE: f32 = 0.3;
+
+/// Turns keys, gamepad buttons and gamepad sticks into `InputEvent`s.
+/// ggez-goodies only does keys, so we handle the pad ourselves.
+pub struct InputBinding {
+    keys: input::InputBinding<Axis, Button>,
+    buttons: HashMap<PadButton, InputEvent>,
+    /// Which of our axes each stick axis is, and whether it's
+    /// backwards; SDL has Y going down but `Axis::Vert` has it up.
+    axes: HashMap<PadAxis, (Axis, bool)>,
+}
+
+impl InputBinding {
+    fn new(keys: input::InputBinding<Axis, Button>) -> Self {
+        let buttons = [
+            (PadButton::A, InputEffect::Button(Button::Jump)),
+            (PadButton::Start, InputEffect::Button(Button::Menu)),
+            (PadButton::DPadUp, InputEffect::Axis(Axis::Vert, true)),
+            (PadButton::DPadDown, InputEffect::Axis(Axis::Vert, false)),
+            (PadButton::DPadLeft, InputEffect::Axis(Axis::Horz, false)),
+            (PadButton::DPadRight, InputEffect::Axis(Axis::Horz, true)),
+        ].iter()
+            .cloned()
+            .collect();
+        let axes = [
+            (PadAxis::LeftX, (Axis::Horz, false)),
+            (PadAxis::LeftY, (Axis::Vert, true)),
+        ].iter()
+            .cloned()
+            .collect();
+        Self {
+            keys,
+            buttons,
+            axes,
+        }
+    }
+
+    pub fn resolve(&self, keycode: Keycode) -> Option<InputEvent> {
+        self.keys.resolve(keycode)
+    }
+
+    pub fn resolve_button(&self, button: PadButton) -> Option<InputEvent> {
+        self.buttons.get(&button).cloned()
+    }
+
+    /// Which way, if any, a stick at the given position is pushing
+    /// one of our axes.  Inside the deadzone it's `Some((axis, None))`.
+    fn resolve_axis(&self, axis: PadAxis, value: i16) -> Option<(Axis, Option<bool>)> {
+        let &(our_axis, flipped) = self.axes.get(&axis)?;
+        let amount = f32::from(value) / f32::from(i16::max_value());
+        if amount.abs() < DEADZONE {
+            Some((our_axis, None))
+        } else {
+            Some((our_axis, Some((amount > 0.0) != flipped)))
+        }
+    }
+}
+
+/// Remembers which way each stick axis on each pad was last pushed,
+/// so stick movement can turn into presses and releases like a key
+/// would.  With more than one pad, a direction stays pressed as long
+/// as any of them is pushing it.
+#[derive(Debug, Default)]
+pub struct StickState {
+    /// Which of our axes each pushed stick axis is pushing, and which
+    /// way, by pad instance ID and stick axis.
+    pushed: HashMap<(i32, PadAxis), (Axis, bool)>,
+}
+
+impl StickState {
+    /// Takes a new stick position from the given pad and returns the
+    /// input events it makes, as `(event, started)`; nothing until it
+    /// crosses into or out of the deadzone, and a release then a press
+    /// if it flips straight from one side to the other.
+    pub fn update(
+        &mut self,
+        binding: &InputBinding,
+        pad: i32,
+        axis: PadAxis,
+        value: i16,
+    ) -> Vec<(InputEvent, bool)> {
+        let (our_axis, direction) = match binding.resolve_axis(axis, value) {
+            Some(resolved) => resolved,
+            None => return Vec::new(),
+        };
+        let old = self.pushed.get(&(pad, axis)).map(|&(_, old)| old);
+        if old == direction {
+            return Vec::new();
+        }
+        let mut events = Vec::new();
+        if let Some(old) = old {
+            self.pushed.remove(&(pad, axis));
+            if !self.is_pushed(our_axis, old) {
+                events.push((InputEffect::Axis(our_axis, old), false));
+            }
+        }
+        if let Some(new) = direction {
+            if !self.is_pushed(our_axis, new) {
+                events.push((InputEffect::Axis(our_axis, new), true));
+            }
+            self.pushed.insert((pad, axis), (our_axis, new));
+        }
+        events
+    }
+
+    /// Whether any stick on any pad is pushing this way.
+    fn is_pushed(&self, axis: Axis, direction: bool) -> bool {
+        self.pushed.values().any(|&pushed| pushed == (axis, direction))
+    }
+}
+
+/// Everything a key can be bound to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Jump,
+    Menu,
+}
+
+/// Every action there is, in the order the rebinding screen lists them.
+pub const ACTIONS: [Action; 6] = [
+    Action::Up,
+    Action::Down,
+    Action::Left,
+    Action::Right,
+    Action::Jump,
+    Action::Menu,
+];
+
+impl Action {
+    fn default_key(self) -> Keycode {
+        match self {
+            Action::Up => Keycode::Up,
+            Action::Down => Keycode::Down,
+            Action::Left => Keycode::Left,
+            Action::Right => Keycode::Right,
+            Action::Jump => Keycode::Z,
+            Action::Menu => Keycode::Escape,
+        }
+    }
+
+    fn bind(
+        self,
+        binding: input::InputBinding<Axis, Button>,
+        key: Keycode,
+    ) -> input::InputBinding<Axis, Button> {
+        match self {
+            Action::Up => binding.bind_key_to_axis(key, Axis::Vert, true),
+            Action::Down => binding.bind_key_to_axis(key, Axis::Vert, false),
+            Action::Left => binding.bind_key_to_axis(key, Axis::Horz, false),
+            Action::Right => binding.bind_key_to_axis(key, Axis::Horz, true),
+            Action::Jump => binding.bind_key_to_button(key, Button::Jump),
+            Action::Menu => binding.bind_key_to_button(key, Button::Menu),
+        }
+    }
+}
+
+/// Which key does what, by SDL key name (like `"Z"` or `"Left Shift"`)
+/// so the settings file is easy to edit by hand.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyBindings {
+    keys: BTreeMap<Action, String>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let keys = ACTIONS
+            .iter()
+            .map(|action| (*action, action.default_key().name()))
+            .collect();
+        Self { keys }
+    }
+}
+
+impl KeyBindings {
+    /// Gives anything that isn't bound its default key, so a
+    /// settings file from before an action existed still works.
+    pub fn fill_defaults(&mut self) {
+        for (action, key) in Self::default().keys {
+            self.keys.entry(action).or_insert(key);
+        }
+    }
+
+    /// The name of the key bound to an action.
+    pub fn key_name(&self, action: Action) -> &str {
+        self.keys.get(&action).map(|s| s.as_str()).unwrap_or("?")
+    }
+
+    /// The key bound to an action, if it's one SDL knows about.
+    pub fn key(&self, action: Action) -> Option<Keycode> {
+        self.keys.get(&action).and_then(|name| Keycode::from_name(name))
+    }
+
+    /// Returns a copy of these bindings with the given key doing
+    /// the given action instead.
+    pub fn rebound(&self, action: Action, key: Keycode) -> Self {
+        let mut new = self.clone();
+        new.keys.insert(action, key.name());
+        new
+    }
+
+    /// Checks that every key name is one SDL knows about and no key
+    /// is bound to more than one action.  The error lists every
+    /// problem, not just the first.
+    pub fn validate(&self) -> Result<(), Err> {
+        let mut problems = Vec::new();
+        let mut used: HashMap<Keycode, Action> = HashMap::new();
+        for (action, name) in &self.keys {
+            match Keycode::from_name(name) {
+                Some(key) => {
+                    if let Some(other) = used.insert(key, *action) {
+                        problems.push(format!(
+                            "{} is bound to both {:?} and {:?}",
+                            name, other, action
+                        ));
+                    }
+                }
+                None => problems.push(format!("{:?} is bound to unknown key {:?}", action, name)),
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Err::BindingError {
+                msg: problems.join("; "),
+            })
+        }
+    }
+
+    /// Makes the binding for these keys, plus the gamepad.  Anything
+    /// bound to a key we don't know just doesn't get bound.
+    pub fn input_binding(&self) -> InputBinding {
+        let keys = self
+            .keys
+            .iter()
+            .fold(input::InputBinding::new(), |binding, (action, name)| {
+                match Keycode::from_name(name) {
+                    Some(key) => action.bind(binding, key),
+                    None => binding,
+                }
+            });
+        InputBinding::new(keys)
+    }
+}
+
 /// Create the default keybindings for our input state.
-pub fn create_input_binding() -> input::InputBinding<Axis, Button> {
-    input::InputBinding::new()
-        .bind_key_to_axis(Keycode::Up, Axis::Vert, true)
-        .bind_key_to_axis(Keycode::Down, Axis::Vert, false)
-        .bind_key_to_axis(Keycode::Left, Axis::Horz, false)
-        .bind_key_to_axis(Keycode::Right, Axis::Horz, true)
-        .bind_key_to_button(Keycode::Z, Button::Jump)
-        .bind_key_to_button(Keycode::Escape, Button::Menu)
+pub fn create_input_binding() -> InputBinding {
+    KeyBindings::default().input_binding()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The default bindings, with some actions bound to other key names.
+    fn bindings(changes: &[(Action, &str)]) -> KeyBindings {
+        let mut bindings = KeyBindings::default();
+        for &(action, name) in changes {
+            bindings.keys.insert(action, name.to_owned());
+        }
+        bindings
+    }
+
+    #[test]
+    fn default_bindings_are_valid() {
+        assert!(KeyBindings::default().validate().is_ok());
+    }
+
+    #[test]
+    fn rebinding_to_a_free_key_is_valid() {
+        let bindings = KeyBindings::default().rebound(Action::Jump, Keycode::Space);
+        assert!(bindings.validate().is_ok());
+        assert_eq!(bindings.key_name(Action::Jump), Keycode::Space.name());
+    }
+
+    #[test]
+    fn unknown_keys_are_invalid() {
+        let err = bindings(&[(Action::Jump, "Not A Key")])
+            .validate()
+            .unwrap_err();
+        assert!(err.to_string().contains("Not A Key"), "{}", err);
+    }
+
+    #[test]
+    fn conflicts_are_invalid() {
+        // Jump is already on Z.
+        let err = KeyBindings::default()
+            .rebound(Action::Menu, Keycode::Z)
+            .validate()
+            .unwrap_err();
+        assert!(err.to_string().contains("both"), "{}", err);
+    }
+
+    #[test]
+    fn every_problem_gets_reported() {
+        let err = bindings(&[
+            (Action::Up, "Nope"),
+            (Action::Down, "Also nope"),
+            (Action::Menu, "Z"),
+        ]).validate()
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("\"Nope\""), "{}", err);
+        assert!(err.contains("\"Also nope\""), "{}", err);
+        assert!(err.contains("both"), "{}", err);
+    }
+
+    #[test]
+    fn sticks_do_nothing_in_the_deadzone() {
+        let binding = create_input_binding();
+        let mut sticks = StickState::default();
+        assert!(sticks.update(&binding, 0, PadAxis::LeftX, 0).is_empty());
+        assert!(sticks.update(&binding, 0, PadAxis::LeftX, 5000).is_empty());
+        assert!(sticks.update(&binding, 0, PadAxis::LeftX, -5000).is_empty());
+    }
+
+    #[test]
+    fn sticks_press_and_release() {
+        let binding = create_input_binding();
+        let mut sticks = StickState::default();
+        let right = InputEffect::Axis(Axis::Horz, true);
+        assert_eq!(
+            sticks.update(&binding, 0, PadAxis::LeftX, 20000),
+            vec![(right, true)]
+        );
+        // Pushing further doesn't press it again.
+        assert!(sticks.update(&binding, 0, PadAxis::LeftX, i16::max_value()).is_empty());
+        assert_eq!(
+            sticks.update(&binding, 0, PadAxis::LeftX, 1000),
+            vec![(right, false)]
+        );
+        assert!(sticks.update(&binding, 0, PadAxis::LeftX, 0).is_empty());
+    }
+
+    #[test]
+    fn sticks_flip_sides() {
+        let binding = create_input_binding();
+        let mut sticks = StickState::default();
+        let right = InputEffect::Axis(Axis::Horz, true);
+        let left = InputEffect::Axis(Axis::Horz, false);
+        sticks.update(&binding, 0, PadAxis::LeftX, 20000);
+        assert_eq!(
+            sticks.update(&binding, 0, PadAxis::LeftX, i16::min_value()),
+            vec![(right, false), (left, true)]
+        );
+        assert_eq!(
+            sticks.update(&binding, 0, PadAxis::LeftX, 0),
+            vec![(left, false)]
+        );
+    }
+
+    #[test]
+    fn sticks_go_up_when_pushed_up() {
+        // SDL has Y going down.
+        let binding = create_input_binding();
+        let mut sticks = StickState::default();
+        assert_eq!(
+            sticks.update(&binding, 0, PadAxis::LeftY, -20000),
+            vec![(InputEffect::Axis(Axis::Vert, true), true)]
+        );
+    }
+
+    #[test]
+    fn unbound_sticks_do_nothing() {
+        let binding = create_input_binding();
+        let mut sticks = StickState::default();
+        assert!(sticks.update(&binding, 0, PadAxis::RightX, 20000).is_empty());
+    }
+
+    #[test]
+    fn sticks_on_different_pads_do_not_fight() {
+        let binding = create_input_binding();
+        let mut sticks = StickState::default();
+        let right = InputEffect::Axis(Axis::Horz, true);
+        let left = InputEffect::Axis(Axis::Horz, false);
+        assert_eq!(
+            sticks.update(&binding, 0, PadAxis::LeftX, 20000),
+            vec![(right, true)]
+        );
+        // The other pad resting, or joining in, changes nothing.
+        assert!(sticks.update(&binding, 1, PadAxis::LeftX, 0).is_empty());
+        assert!(sticks.update(&binding, 1, PadAxis::LeftX, 20000).is_empty());
+        // Right stays held until both let go.
+        assert!(sticks.update(&binding, 0, PadAxis::LeftX, 0).is_empty());
+        assert_eq!(
+            sticks.update(&binding, 1, PadAxis::LeftX, i16::min_value()),
+            vec![(right, false), (left, true)]
+        );
+        assert_eq!(
+            sticks.update(&binding, 1, PadAxis::LeftX, 0),
+            vec![(left, false)]
+        );
+    }
 }
@@ -0,0 +1,103 @@
+//! Collision layers: what sorts of things there are, and which of
+//! them can touch each other.  Everything that gets a collision object
+//! gets its `CollisionGroups` from here, so adding a new sort of thing
+//! means adding a `Layer` and deciding what goes in `INTERACTIONS`.
+
+use ncollide2d as nc;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Layer {
+    Planet,
+    Player,
+    Obstacle,
+}
+
+/// Every layer there is.
+pub const LAYERS: [Layer; 3] = [Layer::Planet, Layer::Player, Layer::Obstacle];
+
+/// Which layers generate contacts with each other.  Order doesn't
+/// matter, and anything not in here ignores each other entirely;
+/// obstacles sit on planets, so they'd be touching them forever.
+const INTERACTIONS: &[(Layer, Layer)] = &[
+    (Layer::Player, Layer::Planet),
+    (Layer::Player, Layer::Obstacle),
+];
+
+impl Layer {
+    /// The ncollide collision group this layer is.
+    fn group(self) -> usize {
+        match self {
+            Layer::Planet => 1,
+            Layer::Player => 2,
+            Layer::Obstacle => 3,
+        }
+    }
+
+    /// Whether things on these two layers should touch.
+    pub fn interacts_with(self, other: Layer) -> bool {
+        INTERACTIONS
+            .iter()
+            .any(|&(a, b)| (a == self && b == other) || (a == other && b == self))
+    }
+
+    /// Builds the `CollisionGroups` for something on this layer.
+    pub fn collision_groups(self) -> nc::world::CollisionGroups {
+        let whitelist: Vec<usize> = LAYERS
+            .iter()
+            .filter(|other| self.interacts_with(**other))
+            .map(|other| other.group())
+            .collect();
+        let mut groups = nc::world::CollisionGroups::new();
+        groups.set_membership(&[self.group()]);
+        groups.set_whitelist(&whitelist);
+        groups
+    }
+
+    /// Whether something with the given `CollisionGroups` is on this layer.
+    pub fn is_in(self, groups: &nc::world::CollisionGroups) -> bool {
+        groups.is_member_of(self.group())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn can_touch(a: Layer, b: Layer) -> bool {
+        a.collision_groups()
+            .can_interact_with_groups(&b.collision_groups())
+    }
+
+    #[test]
+    fn obstacles_ignore_planets() {
+        assert!(!can_touch(Layer::Obstacle, Layer::Planet));
+        assert!(!can_touch(Layer::Planet, Layer::Obstacle));
+    }
+
+    #[test]
+    fn player_touches_planets_and_obstacles() {
+        assert!(can_touch(Layer::Player, Layer::Planet));
+        assert!(can_touch(Layer::Planet, Layer::Player));
+        assert!(can_touch(Layer::Player, Layer::Obstacle));
+        assert!(can_touch(Layer::Obstacle, Layer::Player));
+    }
+
+    #[test]
+    fn groups_match_interactions() {
+        for &a in &LAYERS {
+            assert!(a.is_in(&a.collision_groups()));
+            for &b in &LAYERS {
+                assert_eq!(
+                    can_touch(a, b),
+                    a.interacts_with(b),
+                    "{:?} and {:?} disagree",
+                    a,
+                    b
+                );
+                if a != b {
+                    assert!(!a.is_in(&b.collision_groups()));
+                }
+            }
+        }
+    }
+}
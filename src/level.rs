@@ -0,0 +1,179 @@
+//! Level definitions.  These live in RON files under
+//! `resources/levels/` and get loaded through warmy (see
+//! `resources::Level`), so they hot-reload when you edit them.
+
+use ron;
+
+use std::io;
+
+use components::ControlMode;
+use error::Err;
+use world::Integrator;
+
+/// Everything it takes to set up a level.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LevelDef {
+    pub name: String,
+    /// The player starts on top of the first planet.
+    pub planets: Vec<PlanetDef>,
+    #[serde(default)]
+    pub obstacles: Vec<ObstacleDef>,
+    /// Obstacles to scatter randomly, on top of `obstacles`.
+    #[serde(default)]
+    pub procedural_obstacles: Option<ProceduralObstacles>,
+    pub player: PlayerDef,
+    pub background: BackgroundDef,
+    /// How anything other than the player moves.
+    #[serde(default)]
+    pub integrator: Integrator,
+    /// Whether the player can steer, or just jump.
+    #[serde(default)]
+    pub controls: ControlMode,
+    /// Ways to lose, if any.
+    #[serde(default)]
+    pub fail: FailDef,
+    /// Music to loop while playing the level, if any.
+    #[serde(default)]
+    pub music: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlanetDef {
+    /// Where it is, or where it starts if it's a moon.
+    pub position: (f32, f32),
+    pub radius: f32,
+    /// See `components::Gravity`.
+    pub gravity: f32,
+    /// If set, this is a moon, and starts off in a circular orbit
+    /// around the planet with this index in `LevelDef::planets`.
+    #[serde(default)]
+    pub orbits: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ObstacleDef {
+    /// Index into `LevelDef::planets` of the planet this sits on.
+    #[serde(default)]
+    pub planet: usize,
+    /// Where on the planet it is, in degrees.  0 is to the right
+    /// of the planet, 270 is the top, where the player starts.
+    pub angle: f32,
+    #[serde(default = "default_obstacle_halfwidth")]
+    pub halfwidth: f32,
+}
+
+fn default_obstacle_halfwidth() -> f32 {
+    10.0
+}
+
+/// Obstacles generated from the level seed, spaced so the player
+/// can always jump them; see `procgen::obstacle_angles()`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProceduralObstacles {
+    #[serde(default)]
+    pub planet: usize,
+    pub count: usize,
+    #[serde(default = "default_obstacle_halfwidth")]
+    pub halfwidth: f32,
+}
+
+/// Player tuning; see `components::Player` for what the numbers mean.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerDef {
+    pub halfwidth: f32,
+    pub halfheight: f32,
+    pub jump_force: f32,
+    pub run_acceleration: f32,
+    pub run_force: f32,
+    /// How fast the player's going when the level starts, in pixels/s.
+    pub start_speed: f32,
+}
+
+/// Ways the player can lose a level.  Anything left out can't happen.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FailDef {
+    /// Seconds the player has to make it into orbit.
+    #[serde(default)]
+    pub time_limit: Option<f32>,
+    /// How many obstacles the player can hit; one more and it's over.
+    #[serde(default)]
+    pub max_obstacle_hits: Option<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BackgroundDef {
+    pub stars: usize,
+    /// Stars get scattered over a square this many pixels across.
+    pub bounds: f32,
+    pub star_size: f32,
+}
+
+impl LevelDef {
+    /// Parses a level out of RON, and makes sure it makes sense.
+    pub fn from_reader<R: io::Read>(reader: R) -> Result<Self, Err> {
+        let def: LevelDef = ron::de::from_reader(reader)?;
+        def.validate()?;
+        Ok(def)
+    }
+
+    /// Checks for anything that would keep the level from being built,
+    /// like things on planets that don't exist.
+    pub fn validate(&self) -> Result<(), Err> {
+        if self.planets.is_empty() {
+            return Err(Err::LevelError {
+                msg: format!("Level {} has no planets", self.name),
+            });
+        }
+        if self.planets[0].orbits.is_some() {
+            return Err(Err::LevelError {
+                msg: format!("Level {} starts the player on a moon", self.name),
+            });
+        }
+        for (i, planet) in self.planets.iter().enumerate() {
+            if let Some(parent) = planet.orbits {
+                if parent >= self.planets.len() || parent == i {
+                    return Err(Err::LevelError {
+                        msg: format!(
+                            "Level {} has a moon orbiting planet {}, which can't be right",
+                            self.name, parent
+                        ),
+                    });
+                }
+            }
+        }
+        for obstacle in &self.obstacles {
+            self.validate_obstacle_planet(obstacle.planet, "an obstacle")?;
+        }
+        if let Some(ref procedural) = self.procedural_obstacles {
+            self.validate_obstacle_planet(procedural.planet, "procedural obstacles")?;
+        }
+        if let Some(limit) = self.fail.time_limit {
+            if !(limit > 0.0) {
+                return Err(Err::LevelError {
+                    msg: format!("Level {} has a time limit of {}", self.name, limit),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Obstacles have to go on a planet that exists, and that
+    /// isn't a moon, since they don't move along with it.
+    fn validate_obstacle_planet(&self, planet: usize, what: &str) -> Result<(), Err> {
+        match self.planets.get(planet) {
+            None => Err(Err::LevelError {
+                msg: format!(
+                    "Level {} has {} on planet {}, which doesn't exist",
+                    self.name, what, planet
+                ),
+            }),
+            Some(p) if p.orbits.is_some() => Err(Err::LevelError {
+                msg: format!(
+                    "Level {} has {} on planet {}, which is a moon",
+                    self.name, what, planet
+                ),
+            }),
+            Some(_) => Ok(()),
+        }
+    }
+}
@@ -5,6 +5,7 @@ extern crate chrono;
 #[macro_use]
 extern crate failure;
 extern crate fern;
+extern crate flate2;
 extern crate ggez;
 extern crate ggez_goodies;
 #[macro_use]
@@ -12,6 +13,11 @@ extern crate log;
 extern crate nalgebra;
 extern crate ncollide2d;
 extern crate rand;
+extern crate ron;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate shrev;
 extern crate specs;
 #[macro_use]
 extern crate specs_derive;
@@ -28,15 +34,27 @@ use ggez::timer;
 use std::path;
 
 // Modules that define actual content
+mod campaign;
 mod components;
 mod scenes;
+mod simulation;
 mod systems;
 mod world;
 
 // Modules that define utility stuff.
+mod aseprite;
+mod audio;
 mod error;
+mod events;
+mod ghost;
 mod input;
+mod layers;
+mod level;
+mod procgen;
+mod replay;
 mod resources;
+mod scores;
+mod settings;
 mod util;
 
 /// Function to set up logging.
@@ -81,38 +99,123 @@ fn setup_logger() -> Result<(), fern::InitError> {
 
 /// Main game state.  This holds all our STUFF,
 /// but most of the actual game data are
-/// in `Scenes`, and the `FSceneStack` contains them
+/// in `Scenes`, and the `SceneManager` contains them
 /// plus global game state.
 pub struct MainState {
-    scenes: scenes::FSceneStack,
-    input_binding: input::InputBinding,
+    scenes: scenes::SceneManager,
+    sticks: input::StickState,
 }
 
 impl MainState {
-    pub fn new(resource_dir: Option<path::PathBuf>, ctx: &mut Context) -> Self {
-        let world = world::World::new(ctx, resource_dir.clone());
-        let mut scenestack = scenes::FSceneStack::new(ctx, world);
-        let level_scene = scenes::level::LevelScene::new(ctx, &mut scenestack.world)
-            .expect("Could not create initial scene?!");
+    pub fn new(args: &Args, resource_dir: Option<path::PathBuf>, ctx: &mut Context) -> Self {
+        let mut world = world::World::new(ctx, resource_dir.clone());
+        world.save_dir = Some(ctx.filesystem.get_user_data_dir().to_owned());
+        world.config_dir = Some(ctx.filesystem.get_user_config_dir().to_owned());
+        Self::load_settings(&mut world, ctx);
+        if let Some(ref dir) = world.save_dir {
+            world.high_scores = scores::HighScores::load(dir);
+        }
+        if let Some(seed) = args.seed {
+            world.seed = seed;
+        }
+        let replay = args.replay.as_ref().and_then(|path| {
+            replay::Replay::load(path)
+                .map_err(|e| error!("Could not load replay {:?}: {}", path, e))
+                .ok()
+        });
+        if let Some(ref replay) = replay {
+            world.seed = replay.seed;
+        }
+        info!("Level seed is {}", world.seed);
+        world.campaign = campaign::Campaign::load(ctx, &mut world.assets, "/levels/campaign.ron")
+            .expect("Could not load campaign?!");
+        if let Some(ref dir) = world.save_dir {
+            if let Err(e) = world.campaign.load_progress(dir) {
+                error!("Could not load campaign progress, starting over: {}", e);
+            }
+        }
+        let music = world.campaign.music();
+        let backend = audio::GgezAudio::new(&mut world.assets, ctx, &music);
+        world.audio = audio::Audio::new(Box::new(backend));
+        world.apply_settings();
+        let mut scenestack = scenes::SceneManager::new(world);
         graphics::set_background_color(ctx, graphics::BLACK);
-        scenestack.push(Box::new(level_scene));
         let menu_scene = scenes::menu::MenuScene::new();
         scenestack.push(Box::new(menu_scene));
+        if let Some(replay) = replay {
+            Self::push_replay(&mut scenestack, replay);
+        }
         MainState {
             scenes: scenestack,
-            input_binding: input::create_input_binding(),
+            sticks: input::StickState::default(),
+        }
+    }
+
+    /// Hands an input event to the input state and the current scene.
+    fn send_input(&mut self, ev: input::InputEvent, started: bool) {
+        self.scenes.world.input.update_effect(ev, started);
+        self.scenes.input(ev, started);
+    }
+
+    /// Loads the player's settings and sets up the window with them.
+    /// Anything broken gets put back to its default.  The rest gets
+    /// applied by `World::apply_settings()` once there's audio to apply
+    /// it to.
+    fn load_settings(world: &mut world::World, ctx: &mut Context) {
+        let dir = match world.config_dir {
+            Some(ref dir) => dir.clone(),
+            None => return,
+        };
+        let mut settings = match settings::Settings::load(&dir) {
+            Ok(settings) => settings,
+            Err(e) => {
+                error!("Could not load settings, using the defaults: {}", e);
+                settings::Settings::default()
+            }
+        };
+        let problems = settings.fix();
+        for problem in &problems {
+            error!("Bad setting: {}", problem);
+        }
+        world.settings = settings;
+        if !problems.is_empty() {
+            world.save_settings();
+        }
+        if let Err(e) = world.settings.apply_window(ctx) {
+            error!("Could not set up the window: {}", e);
+        }
+    }
+
+    /// Starts playing back a replay of whichever campaign level it's for.
+    fn push_replay(scenestack: &mut scenes::SceneManager, replay: replay::Replay) {
+        let world = &mut scenestack.world;
+        let index = world
+            .campaign
+            .level_names()
+            .iter()
+            .position(|name| *name == replay.level);
+        let level = match index.and_then(|i| world.campaign.level(i)) {
+            Some(level) => level,
+            None => {
+                error!("Replay is for level {}, which we don't have", replay.level);
+                return;
+            }
+        };
+        match scenes::level::LevelScene::from_replay(world, level, replay) {
+            Ok(scene) => scenestack.push(Box::new(scene)),
+            Err(e) => error!("Could not start replay: {}", e),
         }
     }
 }
 
 impl EventHandler for MainState {
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        const DESIRED_FPS: u32 = 60;
-        while timer::check_update_time(ctx, DESIRED_FPS) {
+        while timer::check_update_time(ctx, world::DESIRED_FPS) {
             self.scenes.update();
         }
         self.scenes.world.assets.sync(ctx);
-        self.scenes.world.input.update(1.0 / 60.0);
+        let frame_time = timer::duration_to_f64(timer::get_delta(ctx)) as f32;
+        self.scenes.world.input.update(frame_time);
 
         if self.scenes.world.quit {
             info!("Exiting due to world quit flag.");
@@ -134,26 +237,92 @@ impl EventHandler for MainState {
         _ctx: &mut Context,
         keycode: Keycode,
         _keymod: Mod,
-        _repeat: bool,
+        repeat: bool,
     ) {
-        if let Some(ev) = self.input_binding.resolve(keycode) {
-            self.scenes.world.input.update_effect(ev, true);
-            self.scenes.input(ev, true);
+        // Holding a key down shouldn't press it again, or bind the
+        // key that started rebinding to whatever's being rebound.
+        if repeat {
+            return;
+        }
+        if self.scenes.world.capturing_key {
+            self.scenes.world.capturing_key = false;
+            self.scenes.world.captured_key = Some(keycode);
+            return;
+        }
+        if let Some(ev) = self.scenes.world.input_binding.resolve(keycode) {
+            self.send_input(ev, true);
         }
     }
 
     fn key_up_event(&mut self, _ctx: &mut Context, keycode: Keycode, _keymod: Mod, _repeat: bool) {
-        if let Some(ev) = self.input_binding.resolve(keycode) {
-            self.scenes.world.input.update_effect(ev, false);
-            self.scenes.input(ev, false);
+        if let Some(ev) = self.scenes.world.input_binding.resolve(keycode) {
+            self.send_input(ev, false);
+        }
+    }
+
+    fn controller_button_down_event(&mut self, _ctx: &mut Context, btn: Button, _instance_id: i32) {
+        if let Some(ev) = self.scenes.world.input_binding.resolve_button(btn) {
+            self.send_input(ev, true);
+        }
+    }
+
+    fn controller_button_up_event(&mut self, _ctx: &mut Context, btn: Button, _instance_id: i32) {
+        if let Some(ev) = self.scenes.world.input_binding.resolve_button(btn) {
+            self.send_input(ev, false);
+        }
+    }
+
+    fn controller_axis_event(
+        &mut self,
+        _ctx: &mut Context,
+        axis: Axis,
+        value: i16,
+        instance_id: i32,
+    ) {
+        let events = self
+            .sticks
+            .update(&self.scenes.world.input_binding, instance_id, axis, value);
+        for (ev, started) in events {
+            self.send_input(ev, started);
+        }
+    }
+}
+
+/// Options from the command line.
+#[derive(Debug, Default)]
+pub struct Args {
+    seed: Option<u64>,
+    /// A replay file to play back.
+    replay: Option<path::PathBuf>,
+}
+
+/// Pulls what we care about out of the command line.
+/// Not worth dragging in a whole argument parsing crate for.
+fn parse_args() -> Args {
+    let mut args = Args::default();
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--seed" => match iter.next().map(|s| s.parse::<u64>()) {
+                Some(Ok(seed)) => args.seed = Some(seed),
+                _ => warn!("--seed needs a number after it"),
+            },
+            "--replay" => match iter.next() {
+                Some(file) => args.replay = Some(path::PathBuf::from(file)),
+                None => warn!("--replay needs a file after it"),
+            },
+            other => warn!("Unknown argument: {}", other),
         }
     }
+    args
 }
 
 pub fn main() {
     setup_logger().expect("Could not set up logging!");
+    let args = parse_args();
     let mut cb = ContextBuilder::new("ld42", "icefoxen")
         .window_setup(conf::WindowSetup::default().title("Running In To Space"))
+        // The player's settings change this once we can load them.
         .window_mode(conf::WindowMode::default().dimensions(800, 600));
 
     // We add the CARGO_MANIFEST_DIR/resources to the filesystems paths so
@@ -175,7 +344,7 @@ pub fn main() {
     let ctx = &mut cb.build().unwrap();
     // This None could be cargo_path
     // but only in dev mode; blarg.  Need to make the filesystem shit better still.
-    let state = &mut MainState::new(None, ctx);
+    let state = &mut MainState::new(&args, None, ctx);
     if let Err(e) = event::run(ctx, state) {
         println!("Error encountered: {}", e);
     } else {
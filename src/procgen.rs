@@ -0,0 +1,187 @@
+//! Procedural level generation.
+//!
+//! Everything in here takes its randomness from a RNG handed to it,
+//! so the same seed always makes the same level.
+
+use rand::Rng;
+
+use std::f32;
+
+use level::{PlanetDef, PlayerDef, ProceduralObstacles};
+
+/// Where the player starts on a planet, in degrees; see `ObstacleDef::angle`.
+pub const PLAYER_START_ANGLE: f32 = 270.0;
+
+/// How far apart, in radians, obstacles on the given planet have to
+/// be for the player to be able to jump one and land before the next.
+///
+/// The slowest the player can be going is stopped: tumbling after
+/// a hit takes nearly all their speed, and with `ControlMode::Steered`
+/// they can brake whenever they like.  So we leave room to get from a
+/// standstill back up to `start_speed`, then jump at that speed.  We
+/// also assume gravity is what it is at the surface the whole time
+/// they're in the air.
+pub fn min_obstacle_spacing(planet: &PlanetDef, player: &PlayerDef, halfwidth: f32) -> f32 {
+    let surface_gravity = planet.gravity / planet.radius;
+    let air_time = 2.0 * player.jump_force / surface_gravity;
+    let jump_distance = player.start_speed * air_time;
+    // Room for the obstacle itself, plus the player's width on
+    // either side so they can land and take off again.
+    let footprint = 2.0 * halfwidth + 4.0 * player.halfwidth;
+    (run_up_distance(player) + jump_distance + footprint) / planet.radius
+}
+
+/// How much ground it takes to get from a standstill to `start_speed`.
+///
+/// Starting from nothing, `Player::velocity` grows linearly, so the
+/// push it gives grows linearly too and the player's speed goes up
+/// with the square of the time: `v = a t^2 / 2` with `a` being
+/// `run_acceleration * run_force`.  That covers `a t^3 / 6`, or a
+/// third of `v t`.  Someone who's braked until they're pushing
+/// backwards needs a bit longer, but they can always just not.
+fn run_up_distance(player: &PlayerDef) -> f32 {
+    let push = player.run_acceleration * player.run_force;
+    if !(push > 0.0) {
+        return 0.0;
+    }
+    let time = (2.0 * player.start_speed / push).sqrt();
+    player.start_speed * time / 3.0
+}
+
+/// Scatters obstacles around a planet, returning their angles in degrees.
+///
+/// The player's starting point counts as an obstacle for spacing
+/// purposes, so they never start right on top of something.  If the
+/// obstacles can't all fit, we make as many as will.
+pub fn obstacle_angles<R: Rng>(
+    rng: &mut R,
+    obstacles: &ProceduralObstacles,
+    planet: &PlanetDef,
+    player: &PlayerDef,
+) -> Vec<f32> {
+    let min_gap = min_obstacle_spacing(planet, player, obstacles.halfwidth);
+    let full_circle = 2.0 * f32::consts::PI;
+    // One gap after each obstacle plus one after the player's start.
+    let max_count = ((full_circle / min_gap).floor() as usize).saturating_sub(1);
+    let count = if obstacles.count > max_count {
+        warn!(
+            "Can only fit {} jumpable obstacles on planet, not {}",
+            max_count, obstacles.count
+        );
+        max_count
+    } else {
+        obstacles.count
+    };
+
+    // Every gap gets the minimum, then we hand out whatever room is
+    // left over at random.
+    let slack = full_circle - (count + 1) as f32 * min_gap;
+    let weights: Vec<f32> = (0..count + 1).map(|_| rng.gen::<f32>()).collect();
+    let total_weight: f32 = weights.iter().sum();
+
+    let mut angle = PLAYER_START_ANGLE.to_radians();
+    let mut angles = Vec::with_capacity(count);
+    for weight in weights.iter().take(count) {
+        let share = if total_weight > 0.0 {
+            weight / total_weight
+        } else {
+            1.0 / (count + 1) as f32
+        };
+        angle += min_gap + slack * share;
+        angles.push(angle.to_degrees() % 360.0);
+    }
+    angles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::rng_from_seed;
+
+    fn planet() -> PlanetDef {
+        PlanetDef {
+            position: (0.0, 0.0),
+            radius: 1500.0,
+            gravity: 600000.0,
+            orbits: None,
+        }
+    }
+
+    fn player() -> PlayerDef {
+        PlayerDef {
+            halfwidth: 8.0,
+            halfheight: 16.0,
+            jump_force: 180.0,
+            run_acceleration: 0.3,
+            run_force: 18.0,
+            start_speed: 90.0,
+        }
+    }
+
+    fn obstacles(count: usize) -> ProceduralObstacles {
+        ProceduralObstacles {
+            planet: 0,
+            count,
+            halfwidth: 10.0,
+        }
+    }
+
+    /// The gaps between the player's start and each obstacle in turn,
+    /// all the way round and back to the start, in degrees.
+    fn gaps(angles: &[f32]) -> Vec<f32> {
+        let mut offsets = vec![0.0];
+        offsets.extend(
+            angles
+                .iter()
+                .map(|a| ((a - PLAYER_START_ANGLE) % 360.0 + 360.0) % 360.0),
+        );
+        offsets.push(360.0);
+        offsets.windows(2).map(|w| w[1] - w[0]).collect()
+    }
+
+    #[test]
+    fn obstacles_are_always_jumpable() {
+        let (planet, player) = (planet(), player());
+        for &count in &[1, 10, 25, 1000] {
+            let obstacles = obstacles(count);
+            let min_gap = min_obstacle_spacing(&planet, &player, obstacles.halfwidth).to_degrees();
+            for seed in 0..20 {
+                let mut rng = rng_from_seed(seed);
+                let angles = obstacle_angles(&mut rng, &obstacles, &planet, &player);
+                assert!(!angles.is_empty());
+                for gap in gaps(&angles) {
+                    assert!(
+                        gap >= min_gap - 0.001,
+                        "Seed {} with {} obstacles has a gap of {} degrees, need {}",
+                        seed,
+                        count,
+                        gap,
+                        min_gap
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn obstacles_leave_room_to_get_going_again() {
+        // Getting from a stop to 90 pixels/s takes about 5.8 seconds
+        // and 173 pixels, on top of a 81 pixel jump and the widths.
+        let (planet, player) = (planet(), player());
+        let spacing = min_obstacle_spacing(&planet, &player, 10.0) * planet.radius;
+        assert!((spacing - 306.0).abs() < 1.0, "Spacing is {}", spacing);
+        // And it all still fits on the real levels.
+        let angles = obstacle_angles(&mut rng_from_seed(0), &obstacles(25), &planet, &player);
+        assert_eq!(angles.len(), 25);
+    }
+
+    #[test]
+    fn same_seed_same_obstacles() {
+        let (planet, player, obstacles) = (planet(), player(), obstacles(25));
+        for seed in 0..20 {
+            let first = obstacle_angles(&mut rng_from_seed(seed), &obstacles, &planet, &player);
+            let second = obstacle_angles(&mut rng_from_seed(seed), &obstacles, &planet, &player);
+            assert_eq!(first, second);
+        }
+    }
+}
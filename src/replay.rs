@@ -0,0 +1,129 @@
+//! Recording the player's input during a level and playing it back.
+//!
+//! Since a `Simulation` is deterministic given the level, the seed, and
+//! which tick each input happened on, that's all we need to save to be
+//! able to reproduce a run exactly.
+
+use chrono;
+use ggez_goodies::input::InputEffect;
+
+use std::path;
+
+use error::Err;
+use input;
+use util::{self, file_safe_name};
+
+/// An input in a form we can serialize, since `InputEffect` isn't.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RecordedEffect {
+    Axis(input::Axis, bool),
+    Button(input::Button),
+}
+
+impl From<input::InputEvent> for RecordedEffect {
+    fn from(ev: input::InputEvent) -> Self {
+        match ev {
+            InputEffect::Axis(axis, positive) => RecordedEffect::Axis(axis, positive),
+            InputEffect::Button(button) => RecordedEffect::Button(button),
+        }
+    }
+}
+
+impl From<RecordedEffect> for input::InputEvent {
+    fn from(ev: RecordedEffect) -> Self {
+        match ev {
+            RecordedEffect::Axis(axis, positive) => InputEffect::Axis(axis, positive),
+            RecordedEffect::Button(button) => InputEffect::Button(button),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedInput {
+    /// How many ticks the simulation had run when this happened.
+    pub tick: u64,
+    pub effect: RecordedEffect,
+    pub started: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    /// The name of the level, as in `LevelDef::name`.
+    pub level: String,
+    pub seed: u64,
+    pub inputs: Vec<RecordedInput>,
+}
+
+impl Replay {
+    pub fn new(level: &str, seed: u64) -> Self {
+        Self {
+            level: level.to_owned(),
+            seed,
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, tick: u64, ev: input::InputEvent, started: bool) {
+        self.inputs.push(RecordedInput {
+            tick,
+            effect: ev.into(),
+            started,
+        });
+    }
+
+    pub fn load(path: &path::Path) -> Result<Self, Err> {
+        util::load_ron(path)
+    }
+
+    pub fn save(&self, path: &path::Path) -> Result<(), Err> {
+        util::save_ron(path, self)
+    }
+
+    /// Saves the replay into the given directory, with a file name
+    /// made from the level name and the time, and returns where it went.
+    pub fn save_in(&self, dir: &path::Path) -> Result<path::PathBuf, Err> {
+        let file_name = format!(
+            "replay-{}-{}.ron",
+            file_safe_name(&self.level),
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        );
+        let path = dir.join(file_name);
+        self.save(&path)?;
+        Ok(path)
+    }
+}
+
+/// Feeds the inputs of a `Replay` back out tick by tick.
+pub struct ReplayPlayer {
+    replay: Replay,
+    next: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        Self { replay, next: 0 }
+    }
+
+    /// Starts playing back from the beginning again.
+    pub fn rewind(&mut self) {
+        self.next = 0;
+    }
+
+    /// Returns all the inputs that happened on the given tick, in order.
+    /// Ticks have to be asked for in order too.
+    pub fn inputs_for_tick(&mut self, tick: u64) -> Vec<RecordedInput> {
+        let mut inputs = Vec::new();
+        while let Some(input) = self.replay.inputs.get(self.next) {
+            if input.tick > tick {
+                break;
+            }
+            if input.tick < tick {
+                warn!("Skipping replay input from tick {}, we're on {}", input.tick, tick);
+            } else {
+                inputs.push(*input);
+            }
+            self.next += 1;
+        }
+        inputs
+    }
+}
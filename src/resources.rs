@@ -2,11 +2,16 @@
 
 use std::path;
 
+use warmy::key::Key;
+
 use failure::{self, Fail};
 use ggez::{self, audio, graphics};
 use warmy;
 
+use aseprite;
+use components::{AnimationFrame, LoopMode};
 use error::*;
+use level;
 
 /// Warmy hands our `load()` method an absolute path, while ggez takes absolute
 /// paths into its VFS directory.  Warmy needs to know the real absolute path so
@@ -84,13 +89,99 @@ impl warmy::Load<ggez::Context> for SoundData {
     }
 }
 
-/// A wrapper for a ggez Font, so we can implement warmy's `Load` trait on it.
-///
-/// Currently it just forces the font size to 12 pt; we should implement a specific
-/// key type for it that includes a font size.
+/// A warmy key for a font: the file, like an `FSKey`, plus what size
+/// we want it in.  Each size gets cached separately.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FontKey {
+    path: path::PathBuf,
+    size: u32,
+}
+
+impl FontKey {
+    /// `size` is in pixels.
+    pub fn new<P: AsRef<path::Path>>(path: P, size: u32) -> Self {
+        FontKey {
+            path: path.as_ref().to_owned(),
+            size,
+        }
+    }
+}
+
+impl warmy::key::Key for FontKey {
+    fn prepare_key(self, root: &path::Path) -> Self {
+        // Resolve the path exactly the way an `FSKey` would.
+        let fs_key = warmy::FSKey::new(&self.path).prepare_key(root);
+        FontKey {
+            path: fs_key.as_path().to_owned(),
+            size: self.size,
+        }
+    }
+}
+
+impl From<FontKey> for warmy::key::DepKey {
+    fn from(key: FontKey) -> Self {
+        warmy::key::DepKey::Path(key.path)
+    }
+}
+
+/// A ggez font at a particular size, so we can implement warmy's `Load`
+/// trait on it.
 #[derive(Debug, Clone)]
-pub struct Font(pub graphics::Font);
+pub struct Font {
+    pub font: graphics::Font,
+    pub size: u32,
+}
+
 impl warmy::Load<ggez::Context> for Font {
+    type Key = FontKey;
+    type Error = failure::Compat<Err>;
+    fn load(
+        key: Self::Key,
+        store: &mut warmy::Storage<ggez::Context>,
+        ctx: &mut ggez::Context,
+    ) -> Result<warmy::Loaded<Self>, Self::Error> {
+        let path = warmy_to_ggez_path(&key.path, store.root());
+        debug!("Loading font {:?} at size {} from file {:?}", path, key.size, key.path);
+
+        graphics::Font::new_glyph_font(ctx, path)
+            .map(|font| {
+                warmy::Loaded::from(Font {
+                    font,
+                    size: key.size,
+                })
+            })
+            .map_err(|e| Err::from(e).compat())
+    }
+}
+
+impl Font {
+    /// Makes some text in this font.
+    pub fn text(&self, text: &str) -> ggez::GameResult<graphics::TextCached> {
+        let font_id = match self.font {
+            graphics::Font::GlyphFont(font_id) => Some(font_id),
+            _ => None,
+        };
+        graphics::TextCached::new(graphics::TextFragment {
+            text: text.to_owned(),
+            color: None,
+            font_id,
+            scale: Some(graphics::Scale::uniform(self.size as f32)),
+        })
+    }
+}
+
+/// All the frames of an Aseprite file, as ggez images, along with
+/// how long each lasts and the file's animation tags.  Saving the
+/// file in Aseprite hot-reloads it, no exporting needed.
+#[derive(Debug, Clone)]
+pub struct SpriteSheet {
+    pub frames: Vec<graphics::Image>,
+    /// In seconds, one per frame.
+    pub durations: Vec<f32>,
+    pub ase: aseprite::Aseprite,
+}
+
+impl warmy::Load<ggez::Context> for SpriteSheet {
     type Key = warmy::FSKey;
     type Error = failure::Compat<Err>;
     fn load(
@@ -99,10 +190,86 @@ impl warmy::Load<ggez::Context> for Font {
         ctx: &mut ggez::Context,
     ) -> Result<warmy::Loaded<Self>, Self::Error> {
         let path = warmy_to_ggez_path(key.as_path(), store.root());
-        debug!("Loading font {:?} from file {:?}", path, key.as_path());
+        debug!("Loading sprite sheet {:?} from file {:?}", path, key.as_path());
 
-        graphics::Font::new(ctx, path, 12)
-            .map(|x| warmy::Loaded::from(Font(x)))
-            .map_err(|e| Err::from(e).compat())
+        let load = |ctx: &mut ggez::Context| -> Result<SpriteSheet, Err> {
+            let file = ctx.filesystem.open(path)?;
+            let mut ase = aseprite::Aseprite::from_reader(file)?;
+            let mut frames = Vec::with_capacity(ase.frames.len());
+            let mut durations = Vec::with_capacity(ase.frames.len());
+            for frame in &ase.frames {
+                frames.push(graphics::Image::from_rgba8(
+                    ctx,
+                    ase.width,
+                    ase.height,
+                    &frame.rgba,
+                )?);
+                durations.push(frame.duration);
+            }
+            // We've got the images now, no need to keep the pixels around.
+            for frame in &mut ase.frames {
+                frame.rgba = Vec::new();
+            }
+            Ok(SpriteSheet {
+                frames,
+                durations,
+                ase,
+            })
+        };
+        load(ctx)
+            .map(warmy::Loaded::from)
+            .map_err(|e| e.compat())
+    }
+}
+
+impl SpriteSheet {
+    /// Turns the given tag, or the whole file if there's no tag, into
+    /// frames for a `components::Animation`.  Also returns a loop mode
+    /// if the tag asks for a particular one.
+    pub fn animation_frames(
+        &self,
+        key: &str,
+        tag: Option<&str>,
+    ) -> Option<(Vec<AnimationFrame>, Option<LoopMode>)> {
+        let (indices, direction) = self.ase.tag_frames(tag)?;
+        let frames = indices
+            .into_iter()
+            .map(|index| AnimationFrame {
+                image: key.to_owned(),
+                index,
+                duration: self.durations[index],
+            })
+            .collect();
+        let mode = match direction {
+            aseprite::Direction::PingPong => Some(LoopMode::PingPong),
+            _ => None,
+        };
+        Some((frames, mode))
+    }
+}
+
+/// A level definition, so designers can hot-reload levels.
+///
+/// This goes through the ggez filesystem like everything else, so
+/// it finds levels in the same places images and such live.
+#[derive(Debug, Clone)]
+pub struct Level(pub level::LevelDef);
+impl warmy::Load<ggez::Context> for Level {
+    type Key = warmy::FSKey;
+    type Error = failure::Compat<Err>;
+    fn load(
+        key: Self::Key,
+        store: &mut warmy::Storage<ggez::Context>,
+        ctx: &mut ggez::Context,
+    ) -> Result<warmy::Loaded<Self>, Self::Error> {
+        let path = warmy_to_ggez_path(key.as_path(), store.root());
+        debug!("Loading level {:?} from file {:?}", path, key.as_path());
+
+        ctx.filesystem
+            .open(path)
+            .map_err(Err::from)
+            .and_then(level::LevelDef::from_reader)
+            .map(|x| warmy::Loaded::from(Level(x)))
+            .map_err(|e| e.compat())
     }
 }
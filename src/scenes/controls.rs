@@ -0,0 +1,214 @@
+use ggez;
+use ggez::event::Keycode;
+use ggez::graphics;
+use ggez_goodies::input::InputEffect;
+use input::{self, Action, KeyBindings, ACTIONS};
+use scenes::*;
+use world::World;
+
+/// Lets the player pick which key does what.  The rows are every
+/// `Action`, then one to put everything back to the defaults.
+pub struct ControlsScene {
+    selected: usize,
+    /// Set when the player picks a row; the next key pressed gets bound.
+    rebinding: Option<Action>,
+    reset: bool,
+    /// Why the last rebinding didn't work, if it didn't.
+    problem: Option<String>,
+}
+
+impl ControlsScene {
+    pub fn new() -> Self {
+        Self {
+            selected: 0,
+            rebinding: None,
+            reset: false,
+            problem: None,
+        }
+    }
+
+    fn rows() -> usize {
+        ACTIONS.len() + 1
+    }
+
+    /// Binds the key the player just pressed, if they've pressed one.
+    /// Escape or the Menu key backs out without changing anything.
+    fn finish_rebinding(&mut self, gameworld: &mut World) {
+        let action = match self.rebinding {
+            Some(action) => action,
+            None => return,
+        };
+        let key = match gameworld.captured_key.take() {
+            Some(key) => key,
+            None => return,
+        };
+        self.rebinding = None;
+        if key == Keycode::Escape || gameworld.settings.keys.key(Action::Menu) == Some(key) {
+            self.problem = None;
+            return;
+        }
+        let bindings = gameworld.settings.keys.rebound(action, key);
+        match bindings.validate() {
+            Ok(()) => {
+                self.problem = None;
+                gameworld.set_key_bindings(bindings);
+            }
+            Err(e) => self.problem = Some(e.to_string()),
+        }
+    }
+}
+
+impl Scene for ControlsScene {
+    fn update(&mut self, gameworld: &mut World) -> SceneSwitch {
+        self.finish_rebinding(gameworld);
+        if self.reset {
+            self.reset = false;
+            self.problem = None;
+            gameworld.set_key_bindings(KeyBindings::default());
+        }
+        SceneSwitch::None
+    }
+
+    fn draw(&mut self, gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        reset_screen_coordinates(ctx)?;
+        let mut message = String::from("Controls:\n\n");
+        for (i, action) in ACTIONS.iter().enumerate() {
+            let cursor = if i == self.selected { ">" } else { " " };
+            let key = if self.rebinding == Some(*action) {
+                "press a key, or Escape to cancel..."
+            } else {
+                gameworld.settings.keys.key_name(*action)
+            };
+            message += &format!("{} {:?}: {}\n", cursor, action, key);
+        }
+        let cursor = if self.selected == ACTIONS.len() { ">" } else { " " };
+        message += &format!("{} Reset to defaults\n", cursor);
+        if let Some(ref problem) = self.problem {
+            message += &format!("\n{}\n", problem);
+        }
+        message += &format!(
+            "\n\n{}/{} to choose, {} to change, {} to go back.",
+            gameworld.settings.keys.key_name(Action::Up),
+            gameworld.settings.keys.key_name(Action::Down),
+            gameworld.settings.keys.key_name(Action::Jump),
+            gameworld.settings.keys.key_name(Action::Menu),
+        );
+        let t = text(&mut gameworld.assets, ctx, &message, MENU_TEXT_SIZE)?;
+
+        t.queue(ctx, graphics::Point2::new(200.0, 100.0), Some(graphics::WHITE));
+
+        graphics::TextCached::draw_queued(ctx, graphics::DrawParam::default())?;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "ControlsScene"
+    }
+
+    fn input(
+        &mut self,
+        gameworld: &mut World,
+        ev: input::InputEvent,
+        started: bool,
+    ) -> SceneSwitch {
+        if !started {
+            return SceneSwitch::None;
+        }
+        if self.rebinding.is_some() {
+            // Keys go to `finish_rebinding()`, but the pad's Menu
+            // button still needs a way out.
+            if ev == InputEffect::Button(input::Button::Menu) {
+                self.rebinding = None;
+                gameworld.capturing_key = false;
+            }
+            return SceneSwitch::None;
+        }
+        match ev {
+            InputEffect::Axis(input::Axis::Vert, true) if self.selected > 0 => {
+                self.selected -= 1;
+            }
+            InputEffect::Axis(input::Axis::Vert, false) if self.selected + 1 < Self::rows() => {
+                self.selected += 1;
+            }
+            InputEffect::Button(input::Button::Jump) => match ACTIONS.get(self.selected) {
+                Some(action) => {
+                    self.rebinding = Some(*action);
+                    gameworld.captured_key = None;
+                    gameworld.capturing_key = true;
+                }
+                None => self.reset = true,
+            },
+            InputEffect::Button(input::Button::Menu) => return SceneSwitch::Pop,
+            _ => (),
+        }
+        SceneSwitch::None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path;
+
+    fn world() -> World {
+        let resources = path::Path::new(env!("CARGO_MANIFEST_DIR")).join("resources");
+        World::with_resource_dir(resources)
+    }
+
+    /// Picks the row for `action`, as if the player had pressed Jump on it.
+    fn start_rebinding(scene: &mut ControlsScene, world: &mut World, action: Action) {
+        scene.selected = ACTIONS.iter().position(|a| *a == action).unwrap();
+        scene.input(world, InputEffect::Button(input::Button::Jump), true);
+        assert_eq!(scene.rebinding, Some(action));
+        assert!(world.capturing_key);
+    }
+
+    /// Does what `MainState` does with a key while one's being captured.
+    fn press(scene: &mut ControlsScene, world: &mut World, key: Keycode) {
+        world.capturing_key = false;
+        world.captured_key = Some(key);
+        scene.update(world);
+    }
+
+    #[test]
+    fn keys_get_rebound() {
+        let mut world = world();
+        let mut scene = ControlsScene::new();
+        start_rebinding(&mut scene, &mut world, Action::Jump);
+        press(&mut scene, &mut world, Keycode::X);
+        assert_eq!(scene.rebinding, None);
+        assert_eq!(world.settings.keys.key(Action::Jump), Some(Keycode::X));
+    }
+
+    #[test]
+    fn escape_and_menu_cancel_rebinding() {
+        let mut world = world();
+        world.set_key_bindings(KeyBindings::default().rebound(Action::Menu, Keycode::P));
+        let before = world.settings.keys.clone();
+        for &key in &[Keycode::Escape, Keycode::P] {
+            let mut scene = ControlsScene::new();
+            start_rebinding(&mut scene, &mut world, Action::Jump);
+            press(&mut scene, &mut world, key);
+            assert_eq!(scene.rebinding, None, "{:?}", key);
+            assert_eq!(scene.problem, None, "{:?}", key);
+            assert_eq!(world.settings.keys, before, "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn pad_menu_cancels_rebinding() {
+        let mut world = world();
+        let before = world.settings.keys.clone();
+        let mut scene = ControlsScene::new();
+        start_rebinding(&mut scene, &mut world, Action::Left);
+        match scene.input(&mut world, InputEffect::Button(input::Button::Menu), true) {
+            SceneSwitch::None => (),
+            _ => panic!("Cancelling a rebinding left the controls screen"),
+        }
+        assert_eq!(scene.rebinding, None);
+        assert!(!world.capturing_key);
+        scene.update(&mut world);
+        assert_eq!(world.settings.keys, before);
+    }
+}
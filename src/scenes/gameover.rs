@@ -0,0 +1,90 @@
+use ggez;
+use ggez::graphics;
+use ggez_goodies::input::InputEffect;
+use input::{self, Action};
+use scenes::*;
+use simulation::FailReason;
+use world::{LevelCommand, RunStats, World};
+
+/// Shown when the player loses.  Pops back to the `LevelScene`,
+/// leaving it a `LevelCommand` to either restart or quit to the title.
+pub struct GameOverScene {
+    reason: FailReason,
+    stats: RunStats,
+}
+
+impl GameOverScene {
+    pub fn new(reason: FailReason, stats: RunStats) -> Self {
+        Self {
+            reason,
+            stats,
+        }
+    }
+}
+
+impl Scene for GameOverScene {
+    fn update(&mut self, _gameworld: &mut World) -> SceneSwitch {
+        SceneSwitch::None
+    }
+
+    fn draw(&mut self, gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        reset_screen_coordinates(ctx)?;
+
+        let keys = &gameworld.settings.keys;
+        let message = format!(
+            r#"
+Game over!
+
+{}
+
+
+Time: {:0.1} seconds
+Peak velocity: {:0.0}
+Obstacles hit: {}
+
+
+
+Press {} to try again.
+Press {} to quit to the title.
+"#,
+            self.reason.description(),
+            self.stats.time,
+            self.stats.peak_velocity,
+            self.stats.obstacles_hit,
+            keys.key_name(Action::Jump),
+            keys.key_name(Action::Menu),
+        );
+        let t = text(&mut gameworld.assets, ctx, &message, MENU_TEXT_SIZE)?;
+
+        t.queue(ctx, graphics::Point2::new(200.0, 100.0), Some(graphics::WHITE));
+
+        graphics::TextCached::draw_queued(ctx, graphics::DrawParam::default())?;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "GameOverScene"
+    }
+
+    fn transition(&self) -> Transition {
+        Transition::Fade(0.5)
+    }
+
+    fn input(
+        &mut self,
+        gameworld: &mut World,
+        ev: input::InputEvent,
+        started: bool,
+    ) -> SceneSwitch {
+        if !started {
+            return SceneSwitch::None;
+        }
+        let command = match ev {
+            InputEffect::Button(input::Button::Jump) => LevelCommand::Restart,
+            InputEffect::Button(input::Button::Menu) => LevelCommand::QuitToTitle,
+            _ => return SceneSwitch::None,
+        };
+        gameworld.level_command = Some(command);
+        SceneSwitch::Pop
+    }
+}
@@ -1,475 +1,438 @@
 use ggez;
 use ggez::graphics;
-use ggez_goodies::scene;
-use nalgebra as na;
-use ncollide2d as nc;
-use rand;
-use specs::{self, Builder, Join};
+use ggez_goodies::input::InputEffect;
+use rand::Rng;
+use specs::{self, Join};
 use warmy;
 
-use std::f32;
+use std::collections::HashMap;
 
+use audio::SoundEffect;
 use components::*;
 use error::Err;
+use ghost::Trajectory;
 use input;
+use level::{BackgroundDef, LevelDef};
+use replay::{Replay, ReplayPlayer};
 use resources;
 use scenes::*;
-use systems::*;
+use scores::NewBests;
+use simulation::{self, Simulation};
 use util::*;
-use world::World;
+use world::{LevelCommand, RunStats, World};
 
 pub struct LevelScene {
-    done: bool,
-    sprites: Vec<warmy::Res<resources::Image>>,
-    sprite_idx: usize,
-    dispatcher: specs::Dispatcher<'static, 'static>,
-    player_entity: specs::Entity,
-    planet_entity: specs::Entity,
-    camera_focus: Point2,
-    background_mesh: graphics::Mesh,
+    /// Set once the player has made it to orbit, so we stop simulating.
+    won: bool,
+    /// Every image or sprite sheet a `Sprite` or `Animation` has asked
+    /// for so far, by warmy key.  `None` if it wouldn't load.
+    images: HashMap<String, Option<SpriteImage>>,
+    sim: Simulation,
+    background_mesh: Option<graphics::Mesh>,
+    level: warmy::Res<resources::Level>,
+    /// The level we last built `sim` from, or tried to, so we can
+    /// tell when warmy has reloaded it.
+    level_def: LevelDef,
+    /// Everything the player has done this run.
+    recording: Replay,
+    /// If we're playing back a replay, rather than letting the
+    /// player play.
+    playback: Option<ReplayPlayer>,
+    /// Where the player has been this run.
+    trajectory: Trajectory,
+    /// The ghost entity and the best run it's following, if any.
+    ghost: Option<(specs::Entity, Trajectory)>,
 }
 
+/// Something a `Sprite` can point at.
+enum SpriteImage {
+    Image(warmy::Res<resources::Image>),
+    Sheet(warmy::Res<resources::SpriteSheet>),
+}
+
+/// How see-through the ghost is.
+const GHOST_ALPHA: f32 = 0.35;
+
 const CAMERA_WIDTH: f32 = 800.0;
 const CAMERA_HEIGHT: f32 = 600.0;
 
-const PLANET_COLLISION_GROUP: usize = 1;
-const PLAYER_COLLISION_GROUP: usize = 2;
-const OBSTACLE_COLLISION_GROUP: usize = 3;
-
 impl LevelScene {
-    pub fn new(ctx: &mut ggez::Context, world: &mut World) -> Result<Self, Err> {
-        let done = false;
-        let sprite_files = vec![
-            "/images/astromonaut0.png",
-            "/images/astromonaut1.png",
-            "/images/astromonaut0.png",
-            "/images/astromonaut2.png",
-        ];
-        let sprites = sprite_files
-            .iter()
-            .map(|filename| {
-                world
-                    .assets
-                    .get::<_, resources::Image>(&warmy::FSKey::new(filename), ctx)
-                    .unwrap()
-            })
-            .collect();
-
-        let dispatcher = Self::register_systems();
-
-        let planet_radius = 2000.0;
-        let planet_entity = Self::create_planet(ctx, world, planet_radius)?;
-        let player_entity = Self::create_player(ctx, world, planet_radius)?;
-        for _i in 0..20 {
-            let obstacle_offset = rand::random::<f32>() * 2.0 * f32::consts::PI;
-            let _ = Self::create_obstacle(
-                ctx,
-                world,
-                planet_radius,
-                obstacle_offset,
-            )?;
-        }
-
-        let background_mesh = Self::create_background_mesh(ctx)?;
+    pub fn new(world: &mut World, level: warmy::Res<resources::Level>) -> Result<Self, Err> {
+        let level_def = level.borrow().0.clone();
+        let mut sim = Simulation::new(world, &level_def)?;
+        let recording = Replay::new(&level_def.name, world.seed);
+        let trajectory = Trajectory::new(&level_def.name);
+        let ghost = Self::spawn_ghost(world, &mut sim, &level_def.name);
 
         Ok(LevelScene {
-            done,
-            sprites,
-            sprite_idx: 0,
-            dispatcher,
-            player_entity,
-            planet_entity,
-            background_mesh,
-            camera_focus: na::origin(),
+            won: false,
+            images: HashMap::new(),
+            sim,
+            background_mesh: None,
+            level,
+            level_def,
+            recording,
+            playback: None,
+            trajectory,
+            ghost,
         })
     }
 
-    fn register_systems() -> specs::Dispatcher<'static, 'static> {
-        let gravity = GravitySystem {};
-        specs::DispatcherBuilder::new()
-            .with(gravity, "sys_gravity", &[])
-            .with(PlayerTumbleSystem {}, "sys_tumble", &[])
-            // .with(NCollideMotionSystem {}, "sys_motion", &[])
-            // .with(DebugPrinterSystem {}, "sys_debugprint", &[])
-            .build()
+    /// Creates a ghost following the best run through the level,
+    /// if we're doing that and there is one.
+    fn spawn_ghost(
+        world: &mut World,
+        sim: &mut Simulation,
+        level_name: &str,
+    ) -> Option<(specs::Entity, Trajectory)> {
+        if !world.settings.show_ghost {
+            return None;
+        }
+        let best = match world.save_dir {
+            Some(ref dir) => match Trajectory::load_best(dir, level_name) {
+                Ok(best) => best,
+                Err(e) => {
+                    warn!("Could not load ghost for {}: {}", level_name, e);
+                    None
+                }
+            },
+            None => None,
+        }?;
+        let entity = sim.create_ghost(world, GHOST_ALPHA);
+        Some((entity, best))
     }
 
-    fn create_background_mesh(ctx: &mut ggez::Context) -> Result<graphics::Mesh, Err> {
-        let num_stars = 10000;
-        let star_max_bounds = 10000.0;
-        let mut mb = graphics::MeshBuilder::new();
-        for _ in 0..num_stars {
-            let x = rand::random::<f32>() * star_max_bounds - (star_max_bounds / 2.0);
-            let y = rand::random::<f32>() * star_max_bounds - (star_max_bounds / 2.0);
-            mb.circle(
-                graphics::DrawMode::Fill,
-                graphics::Point2::new(x, y),
-                2.0,
-                2.0,
-            );
-        }
-        mb.build(ctx).map_err(Err::from)
+    /// Where the player is and which way they look like they're facing,
+    /// tumbling included.
+    fn player_pose(&self, world: &World) -> (Point2, f32) {
+        let (position, rotation) = self.sim.player_position(world);
+        let sprites = world.specs_world.read_storage::<Sprite>();
+        let extra = sprites
+            .get(self.sim.player_entity)
+            .map(|s| s.rotation)
+            .unwrap_or(0.0);
+        (position, rotation + extra)
     }
 
-    fn create_player(
-        ctx: &mut ggez::Context,
-        world: &mut World,
-        planet_radius: f32,
-    ) -> Result<specs::Entity, Err> {
-        let player_halfwidth = 8.0;
-        let player_halfheight = 16.0;
-        let run_acceleration = 0.005;
-        let player_offset = planet_radius + player_halfheight * 3.0;
-        // Make the player entity
-        let entity = world
-            .specs_world
-            .create_entity()
-            .with(Player {
-                on_ground: false,
-                jumping: false,
-                jump_force: 3.0,
-                velocity: 0.0,
-                run_acceleration,
-                tumbling_timer: 0.0,
-                friction: 0.0,
-            })
-            .with(Motion {
-                velocity: Vector2::new(1.5, 0.0),
-                acceleration: Vector2::new(0.0, 0.0),
-            })
-            .with(Mass {})
-            .with(Sprite {})
-            .build();
-
-        // Player collision info
-        let shape = nc::shape::Cuboid::new(Vector2::new(player_halfwidth, player_halfheight));
-        let mut player_collide_group = nc::world::CollisionGroups::new();
-        player_collide_group.set_membership(&[PLAYER_COLLISION_GROUP]);
-        let query_type = nc::world::GeometricQueryType::Contacts(0.0, 0.0);
-
-        let player_collider = {
-            let mut collide_world = world.specs_world.write_resource::<CollisionWorld>();
-            let player_handle = collide_world.add(
-                na::Isometry2::new(na::Vector2::new(0.0, -player_offset), na::zero()),
-                nc::shape::ShapeHandle::new(shape.clone()),
-                player_collide_group,
-                query_type,
-                entity,
-            );
+    /// Moves the ghost to wherever it was on the current tick.
+    /// Call this after stepping, the same as `trajectory.push()`.
+    fn update_ghost(&self, world: &mut World) {
+        if let Some((ref entity, ref best)) = self.ghost {
+            if let Some(frame) = best.frame(self.sim.tick()) {
+                let mut ghosts = world.specs_world.write_storage::<Ghost>();
+                if let Some(ghost) = ghosts.get_mut(*entity) {
+                    ghost.position = Point2::new(frame.x, frame.y);
+                    ghost.rotation = frame.rotation;
+                }
+            }
+        }
+    }
 
-            Collider {
-                object_handle: player_handle,
+    /// Saves this run as the one to race against, if it's the best yet.
+    fn save_trajectory(&self, world: &World) {
+        if self.playback.is_some() {
+            return;
+        }
+        if let Some(ref dir) = world.save_dir {
+            match self.trajectory.save_if_best(dir) {
+                Ok(true) => info!("New best run for {}!", self.trajectory.level),
+                Ok(false) => (),
+                Err(e) => warn!("Could not save ghost: {}", e),
             }
-        };
-        // Insert the collider.
-        world
-            .specs_world
-            .write_storage::<Collider>()
-            .insert(entity, player_collider)?;
-        Ok(entity)
+        }
     }
 
-    fn create_planet(
-        ctx: &mut ggez::Context,
+    /// Creates a scene that plays back the given replay instead of
+    /// listening to the player.  The world's seed has to match the
+    /// replay's, or the level won't come out the same.
+    pub fn from_replay(
         world: &mut World,
-        planet_radius: f32,
-    ) -> Result<specs::Entity, Err> {
-        let gravity = 200.0;
-        // Make the world entity
-        let entity = world
-            .specs_world
-            .create_entity()
-            .with(Mesh {
-                mesh: graphics::MeshBuilder::default()
-                    .circle(
-                        graphics::DrawMode::Fill,
-                        graphics::Point2::new(0.0, 0.0),
-                        planet_radius,
-                        0.1,
-                    )
-                    .build(ctx)?,
-            })
-            .with(Gravity { force: gravity })
-            .build();
-
-        // Planet collision info
-        let ball = nc::shape::Ball::new(planet_radius);
-        let mut terrain_collide_group = nc::world::CollisionGroups::new();
-        terrain_collide_group.set_membership(&[PLANET_COLLISION_GROUP]);
-        let query_type = nc::world::GeometricQueryType::Contacts(0.0, 0.0);
-
-        let planet_collider = {
-            let mut collide_world = world.specs_world.write_resource::<CollisionWorld>();
-            let planet_handle = collide_world.add(
-                na::Isometry2::new(Vector2::new(0.0, 0.0), na::zero()),
-                nc::shape::ShapeHandle::new(ball.clone()),
-                terrain_collide_group,
-                query_type,
-                entity,
+        level: warmy::Res<resources::Level>,
+        replay: Replay,
+    ) -> Result<Self, Err> {
+        if world.seed != replay.seed {
+            warn!(
+                "Replay was recorded with seed {} but we're using {}",
+                replay.seed, world.seed
             );
+        }
+        let mut scene = Self::new(world, level)?;
+        scene.playback = Some(ReplayPlayer::new(replay));
+        Ok(scene)
+    }
 
-            Collider {
-                object_handle: planet_handle,
+    /// Saves the recording of this run, if there's anywhere to put it.
+    fn save_recording(&self, world: &World) {
+        if self.playback.is_some() {
+            return;
+        }
+        if let Some(ref dir) = world.save_dir {
+            match self.recording.save_in(dir) {
+                Ok(path) => info!("Saved replay to {:?}", path),
+                Err(e) => warn!("Could not save replay: {}", e),
             }
-        };
-        // Insert the collider.
-        world
-            .specs_world
-            .write_storage::<Collider>()
-            .insert(entity, planet_collider)?;
-        Ok(entity)
+        }
     }
 
-    /// Creates an obstacle on the planet at the given angle.
-    /// Assumes the planet is at 0,0 I guess
-    fn create_obstacle(
-        ctx: &mut ggez::Context,
-        world: &mut World,
-        planet_radius: f32,
-        angle: f32,
-    ) -> Result<specs::Entity, Err> {
-        let obstacle_halfwidth = 10.0;
-        let obstacle_offset = planet_radius + obstacle_halfwidth;
-        // Make the player entity
-        let entity = world
-            .specs_world
-            .create_entity()
-            .with(Obstacle {})
-            .with(Mesh {
-                mesh: graphics::MeshBuilder::default()
-                    .polygon(
-                        graphics::DrawMode::Line(2.0),
-                        &[
-                            graphics::Point2::new(-obstacle_halfwidth, -obstacle_halfwidth),
-                            graphics::Point2::new(-obstacle_halfwidth, obstacle_halfwidth),
-                            graphics::Point2::new(obstacle_halfwidth, obstacle_halfwidth),
-                            graphics::Point2::new(obstacle_halfwidth, -obstacle_halfwidth),
-                        ],
-                    )
-                    .build(ctx)?,
-            })
-            .build();
-
-        // collision info
-        let shape = nc::shape::Cuboid::new(Vector2::new(obstacle_halfwidth, obstacle_halfwidth));
-        // TODO: Wait do we create multiple groups here?  I think so...
-        // Also we gotta make sure we keep the things straight.
-        // TODO: Figure out membership; must collide with player but not
-        // the planet.
-        let mut obstacle_collide_group = nc::world::CollisionGroups::new();
-        obstacle_collide_group.set_membership(&[OBSTACLE_COLLISION_GROUP]);
-        // obstacle_collide_group.set_whitelist(&[PLAYER_COLLISION_GROUP]);
-        // obstacle_collide_group.set_blacklist(&[PLANET_COLLISION_GROUP]);
-        let query_type = nc::world::GeometricQueryType::Contacts(0.0, 0.0);
-
-        let obstacle_collider = {
-            let mut collide_world = world.specs_world.write_resource::<CollisionWorld>();
-            // sigh
-            let x = f32::cos(angle) * obstacle_offset;
-            let y = f32::sin(angle) * obstacle_offset;
-            let handle = collide_world.add(
-                na::Isometry2::new(na::Vector2::new(x, y), angle),
-                nc::shape::ShapeHandle::new(shape.clone()),
-                obstacle_collide_group,
-                query_type,
-                entity,
-            );
+    /// Puts this run in the high scores, unless it's a replay.
+    fn record_run(&self, world: &mut World, stats: &RunStats) -> NewBests {
+        if self.playback.is_some() {
+            return NewBests::default();
+        }
+        world.record_run(&self.level_def.name, stats)
+    }
 
-            Collider {
-                object_handle: handle,
+    /// If the level file has been hot-reloaded, throw away the
+    /// current run and start over with the new one.
+    fn reload_if_changed(&mut self, world: &mut World) -> Result<(), Err> {
+        let new_def = {
+            let level = self.level.borrow();
+            if level.0 == self.level_def {
+                return Ok(());
             }
+            level.0.clone()
         };
-        // Insert the collider.
+        info!("Level {} changed, rebuilding it", new_def.name);
+        let result = self.restart_with(world, &new_def);
+        // If it won't build we keep playing the old one, but there's
+        // no point trying the same thing again every frame.
+        self.level_def = new_def;
+        self.background_mesh = None;
+        result
+    }
+
+    /// Starts the level over.
+    fn restart(&mut self, world: &mut World) -> Result<(), Err> {
+        let level_def = self.level_def.clone();
+        self.restart_with(world, &level_def)
+    }
+
+    /// Starts a new run of `level_def`.  If it won't build, the current
+    /// run carries on.  Otherwise the run so far still gets its
+    /// recording saved, since failed runs are the interesting ones.
+    fn restart_with(&mut self, world: &mut World, level_def: &LevelDef) -> Result<(), Err> {
+        self.sim.restart(world, level_def)?;
+        self.save_recording(world);
+        self.ghost = Self::spawn_ghost(world, &mut self.sim, &level_def.name);
+        self.trajectory = Trajectory::new(&level_def.name);
+        self.won = false;
+        self.recording = Replay::new(&level_def.name, world.seed);
+        if let Some(ref mut playback) = self.playback {
+            playback.rewind();
+        }
         world
-            .specs_world
-            .write_storage::<Collider>()
-            .insert(entity, obstacle_collider)?;
-        Ok(entity)
+            .audio
+            .play_music(level_def.music.as_ref().map(|m| m.as_str()));
+        Ok(())
     }
 
-    fn handle_contact_events(&mut self, gameworld: &mut World) {
-        let mut collide_world = gameworld.specs_world.write_resource::<CollisionWorld>();
-        collide_world.update();
-        let mut player_storage = gameworld.specs_world.write_storage::<Player>();
-
-        // Save and reuse the same vec each run of the loop so we only allocate once.
-        let contacts_list = &mut Vec::new();
-        for e in collide_world.contact_events() {
-            contacts_list.clear();
-            match e {
-                nc::events::ContactEvent::Started(cobj_handle1, cobj_handle2) => {
-                    // It's apparently possible for the collision pair to have
-                    // no contacts...
-                    // Possibly if one object is entirely inside another?
-                    if let Some(pair) = (&*collide_world).contact_pair(*cobj_handle1, *cobj_handle2)
-                    {
-                        pair.contacts(contacts_list);
-                        let cobj1 = collide_world
-                            .collision_object(*cobj_handle1)
-                            .expect("Invalid collision object handle?");
-                        let cobj2 = collide_world
-                            .collision_object(*cobj_handle2)
-                            .expect("Invalid collision object handle?");
-
-                        // Get the entities out of the collision data
-                        let mut do_collision =
-                            |cobj1: &CollisionObject, cobj2: &CollisionObject| {
-                                let e1 = cobj1.data();
-                                if let Some(player) = player_storage.get_mut(*e1) {
-                                    // Are we colliding with terrain?
-                                    if cobj2
-                                        .collision_groups()
-                                        .is_member_of(PLANET_COLLISION_GROUP)
-                                    {
-                                        player.on_ground = true;
-                                    } else if cobj2
-                                        .collision_groups()
-                                        .is_member_of(OBSTACLE_COLLISION_GROUP)
-                                        && (player.tumbling_timer <= 0.0)
-                                    {
-                                        player.tumbling_timer = 5.0;
-                                    }
-                                }
-                            };
-
-                        // Same query twice, just inverted...
-                        // TODO: this is annoying, how do we resolve this?
-                        do_collision(cobj1, cobj2);
-                        do_collision(cobj2, cobj1);
-                    }
+    /// Lets go of everything the player might be holding down, so
+    /// nothing's stuck on when we come back from the pause menu
+    /// or whatever else got pushed over us.
+    /// The releases get recorded like any other input so replays
+    /// still come out the same.
+    fn release_inputs(&mut self, world: &mut World) {
+        if self.playback.is_some() {
+            return;
+        }
+        let held = [
+            InputEffect::Button(input::Button::Jump),
+            InputEffect::Axis(input::Axis::Horz, true),
+            InputEffect::Axis(input::Axis::Horz, false),
+            InputEffect::Axis(input::Axis::Vert, true),
+            InputEffect::Axis(input::Axis::Vert, false),
+        ];
+        for ev in &held {
+            self.recording.record(self.sim.tick(), *ev, false);
+            self.sim.input(world, *ev, false);
+        }
+    }
+
+    /// Does whatever the pause menu or game over screen asked for,
+    /// once it's gone.
+    fn handle_level_command(&mut self, world: &mut World) -> Option<SceneSwitch> {
+        match world.level_command.take()? {
+            LevelCommand::Restart => {
+                if let Err(e) = self.restart(world) {
+                    error!("Could not restart level: {}", e);
                 }
-                nc::events::ContactEvent::Stopped(cobj_handle1, cobj_handle2) => {
-                    if let Some(pair) = (&*collide_world).contact_pair(*cobj_handle1, *cobj_handle2)
-                    {
-                        pair.contacts(contacts_list);
-                        let cobj1 = collide_world
-                            .collision_object(*cobj_handle1)
-                            .expect("Invalid collision object handle?");
-                        let cobj2 = collide_world
-                            .collision_object(*cobj_handle2)
-                            .expect("Invalid collision object handle?");
-
-                        // Get the entities out of the collision data
-                        let mut do_collision =
-                            |cobj1: &CollisionObject, cobj2: &CollisionObject| {
-                                let e1 = cobj1.data();
-                                if let Some(player) = player_storage.get_mut(*e1) {
-                                    // Are we colliding with terrain?
-                                    if cobj2
-                                        .collision_groups()
-                                        .is_member_of(PLANET_COLLISION_GROUP)
-                                    {
-                                        player.on_ground = false;
-                                    }
-                                }
-                            };
-
-                        // Same query twice, just inverted...
-                        // TODO: this is annoying, how do we resolve this?
-                        do_collision(cobj1, cobj2);
-                        do_collision(cobj2, cobj1);
-                    }
+                None
+            }
+            LevelCommand::QuitGame => {
+                self.save_recording(world);
+                world.quit = true;
+                None
+            }
+            LevelCommand::QuitToTitle => {
+                self.save_recording(world);
+                self.sim.destroy(world);
+                Some(SceneSwitch::Pop)
+            }
+        }
+    }
+
+    /// Creates the scene for the given level of the campaign,
+    /// and makes it the current one.
+    pub fn from_campaign(world: &mut World, index: usize) -> Result<Self, Err> {
+        let level = world.campaign.select(index).ok_or_else(|| Err::LevelError {
+            msg: format!("Campaign level {} doesn't exist or is locked", index),
+        })?;
+        // Picking a level can unlock it or change the current one.
+        world.save_progress();
+        Self::new(world, level)
+    }
+
+    /// Called once the player has won and seen the victory screen;
+    /// unlocks the next level in the campaign and moves on to it if
+    /// `keep_playing`, or goes back to the menu if not or there isn't one.
+    fn next_level(&mut self, world: &mut World, keep_playing: bool) -> SceneSwitch {
+        self.sim.destroy(world);
+        if self.playback.is_some() {
+            // Watching a replay doesn't count as beating the level.
+            return SceneSwitch::Pop;
+        }
+        match world.campaign.complete_current() {
+            Some(next) if keep_playing => match LevelScene::from_campaign(world, next) {
+                Ok(scene) => SceneSwitch::Replace(Box::new(scene)),
+                Err(e) => {
+                    error!("Could not start next level: {}", e);
+                    SceneSwitch::Pop
                 }
+            },
+            Some(_) => {
+                world.save_progress();
+                SceneSwitch::Pop
+            }
+            None => {
+                info!("Campaign finished!");
+                SceneSwitch::Pop
             }
         }
     }
 
-    /// This is really hard to express as a specs System so we roll our own.
-    fn run_player_motion(&mut self, world: &mut World) {
-        if let Some(player) = world
-            .specs_world
-            .write_storage::<Player>()
-            .get_mut(self.player_entity)
+    /// Loads the things that need a `Context` to exist, if
+    /// we haven't already.
+    fn load_graphics(&mut self, world: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
         {
-            let mut colliders = world.specs_world.write_storage::<Collider>();
-            let mut motions = world.specs_world.write_storage::<Motion>();
-            let mut ncollide_world = world.specs_world.write_resource::<CollisionWorld>();
-
-            let player_motion = motions
-                .get_mut(self.player_entity)
-                .expect("Player w/o motion?");
-            let player_collider = colliders
-                .get(self.player_entity)
-                .expect("Player w/o motion?");
-            let (player_position, _player_rotation) =
-                collision_object_position(&*ncollide_world, &player_collider);
-            let planet_collider = colliders
-                .get(self.planet_entity)
-                .expect("Planet w/o collider?");
-            let (planet_position, _planet_rotation) =
-                collision_object_position(&*ncollide_world, planet_collider);
-
-            let offset = player_position - planet_position;
-            let normal = offset / na::norm(&offset);
-            if player.on_ground {
-                // We only want to zero the Y component
-                // of the velocity... that is, the portion
-                // towards the planet.
-                // So we take the projection of velocity onto
-                // the toward-the-planet offset vector.
-                let projection = na::dot(&player_motion.velocity, &(offset / na::norm(&offset)));
-                // debug!("Projection is {:?}, offset is {:?}, velocity is {}", projection, offset, player_motion.velocity);
-
-                player_motion.velocity -= na::normalize(&offset) * projection;
-
-                player_motion.acceleration = na::zero();
-                // Jump
-                if player.jumping {
-                    player_motion.acceleration += normal * player.jump_force;
-                    player.on_ground = false;
+            let sprites = world.specs_world.read_storage::<Sprite>();
+            let mut animations = world.specs_world.write_storage::<Animation>();
+            for sprite in sprites.join() {
+                self.load_image(&mut world.assets, ctx, &sprite.image);
+            }
+            // Sprite sheet animations get their frames from the sheet,
+            // every time in case it's been reloaded.
+            for animation in (&mut animations).join() {
+                let (key, tag) = match animation.sheet {
+                    Some((ref key, ref tag)) => (key.clone(), tag.clone()),
+                    None => continue,
+                };
+                self.load_image(&mut world.assets, ctx, &key);
+                if let Some(&Some(SpriteImage::Sheet(ref sheet))) = self.images.get(&key) {
+                    match sheet.borrow().animation_frames(&key, tag.as_ref().map(|t| t.as_str())) {
+                        Some((frames, mode)) => {
+                            animation.set_frames(frames);
+                            if let Some(mode) = mode {
+                                animation.mode = mode;
+                            }
+                        }
+                        None => warn!("Sprite sheet {} has no tag {:?}", key, tag),
+                    }
                 }
-
-                // Walk
-                let rot = na::Rotation2::new(f32::consts::PI / 2.0);
-                let run_speed = rot * (normal * player.velocity);
-                player_motion.acceleration += run_speed * player.run_acceleration;
             }
-            // The friction term is probably wrong since it will probably slow falling
-            // as well, but fuck it, it doesn't seem to make the player go backwards.
-            player.velocity += player.run_acceleration - (player.velocity * player.friction);
-
-            player_motion.velocity +=
-                player_motion.acceleration - (player_motion.velocity * player.friction);
-            player_motion.acceleration = na::zero();
-
-            // Rotate to stand upright on planet.
-            let player_angle = f32::atan2(offset.x, -offset.y);
-
-            let new_position = {
-                let collision_obj = ncollide_world
-                    .collision_object(player_collider.object_handle)
-                    .expect(
-                        "Invalid collision object; was it removed from ncollide but not specs?",
-                    );
-                let mut new_position = collision_obj.position().clone();
-                new_position
-                    .append_translation_mut(&na::Translation::from_vector(player_motion.velocity));
-                new_position.rotation = na::UnitComplex::from_angle(player_angle);
-                new_position
-            };
-            ncollide_world.set_position(player_collider.object_handle, new_position);
-            self.camera_focus = Point2::new(
-                new_position.translation.vector.x,
-                new_position.translation.vector.y,
+        }
+        if self.background_mesh.is_none() {
+            self.background_mesh = Some(Self::create_background_mesh(
+                ctx,
+                &self.level_def.background,
+                world.seed,
+            )?);
+        }
+        let mut meshes = world.specs_world.write_storage::<Mesh>();
+        for mesh in (&mut meshes).join() {
+            if mesh.mesh.is_none() {
+                mesh.mesh = Some(build_mesh(ctx, mesh.shape)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads an image or sprite sheet into `images` if it isn't already.
+    fn load_image(
+        &mut self,
+        store: &mut warmy::Store<ggez::Context>,
+        ctx: &mut ggez::Context,
+        key: &str,
+    ) {
+        if self.images.contains_key(key) {
+            return;
+        }
+        let fs_key = warmy::FSKey::new(key);
+        let image = if is_sprite_sheet(key) {
+            store
+                .get::<_, resources::SpriteSheet>(&fs_key, ctx)
+                .map(SpriteImage::Sheet)
+                .map_err(|e| error!("Could not load sprite sheet {}: {}", key, e))
+                .ok()
+        } else {
+            store
+                .get::<_, resources::Image>(&fs_key, ctx)
+                .map(SpriteImage::Image)
+                .map_err(|e| error!("Could not load sprite image {}: {}", key, e))
+                .ok()
+        };
+        self.images.insert(key.to_owned(), image);
+    }
+
+    fn create_background_mesh(
+        ctx: &mut ggez::Context,
+        background: &BackgroundDef,
+        seed: u64,
+    ) -> ggez::GameResult<graphics::Mesh> {
+        let num_stars = background.stars;
+        let star_max_bounds = background.bounds;
+        // The stars get their own RNG rather than using the `GameRng`
+        // resource, so whether or not we draw anything can't change
+        // what happens in the simulation.
+        let mut rng = rng_from_seed(seed);
+        let mut mb = graphics::MeshBuilder::new();
+        for _ in 0..num_stars {
+            let x = rng.gen::<f32>() * star_max_bounds - (star_max_bounds / 2.0);
+            let y = rng.gen::<f32>() * star_max_bounds - (star_max_bounds / 2.0);
+            mb.circle(
+                graphics::DrawMode::Fill,
+                graphics::Point2::new(x, y),
+                background.star_size,
+                2.0,
             );
         }
+        mb.build(ctx)
     }
 }
 
-/// Takes a collision object handle and returns the location and orientation
-/// of the object.
-fn collision_object_position(
-    ncollide_world: &CollisionWorld,
-    collider: &Collider,
-) -> (Point2, f32) {
-    let collision_object = ncollide_world
-        .collision_object(collider.object_handle)
-        .expect("Invalid collision object; was it removed from ncollide but not specs?");
-    let isometry = collision_object.position();
-    let annoying_new_pos =
-        Point2::new(isometry.translation.vector.x, isometry.translation.vector.y);
-    let annoying_new_angle = isometry.rotation.angle();
-    (annoying_new_pos, annoying_new_angle)
+/// Turns a `MeshShape` into something we can actually draw.
+fn build_mesh(ctx: &mut ggez::Context, shape: MeshShape) -> ggez::GameResult<graphics::Mesh> {
+    let mesh = match shape {
+        MeshShape::Circle { radius } => graphics::MeshBuilder::default()
+            .circle(
+                graphics::DrawMode::Fill,
+                graphics::Point2::new(0.0, 0.0),
+                radius,
+                0.1,
+            )
+            .build(ctx)?,
+        MeshShape::Square { halfwidth } => graphics::MeshBuilder::default()
+            .polygon(
+                graphics::DrawMode::Line(2.0),
+                &[
+                    graphics::Point2::new(-halfwidth, -halfwidth),
+                    graphics::Point2::new(-halfwidth, halfwidth),
+                    graphics::Point2::new(halfwidth, halfwidth),
+                    graphics::Point2::new(halfwidth, -halfwidth),
+                ],
+            )
+            .build(ctx)?,
+    };
+    Ok(mesh)
 }
 
 /// augh
@@ -478,64 +441,131 @@ fn collision_object_position(
 fn ggez_collision_object_position(
     ncollide_world: &CollisionWorld,
     collider: &Collider,
-) -> (graphics::Point2, f32) {
-    let (point, rotation) = collision_object_position(ncollide_world, collider);
-    (graphics::Point2::new(point.x, point.y), rotation)
+) -> Option<(graphics::Point2, f32)> {
+    let (point, rotation) = simulation::collision_object_position(ncollide_world, collider)?;
+    Some((graphics::Point2::new(point.x, point.y), rotation))
 }
 
-impl scene::Scene<World, input::InputEvent> for LevelScene {
-    fn update(&mut self, gameworld: &mut World) -> FSceneSwitch {
-        self.run_player_motion(gameworld);
-        self.dispatcher.dispatch(&mut gameworld.specs_world.res);
-
-        self.handle_contact_events(gameworld);
-        self.sprite_idx += 1;
-        if self.done {
-            scene::SceneSwitch::Pop
+impl Scene for LevelScene {
+    fn update(&mut self, gameworld: &mut World) -> SceneSwitch {
+        if let Err(e) = self.reload_if_changed(gameworld) {
+            error!("Could not rebuild reloaded level: {}", e);
+        }
+        if self.won {
+            // We only get updated again once the victory screen is gone,
+            // which leaves `QuitToTitle` if the player's had enough.
+            let keep_playing = gameworld.level_command.take() != Some(LevelCommand::QuitToTitle);
+            return self.next_level(gameworld, keep_playing);
+        }
+        if let Some(switch) = self.handle_level_command(gameworld) {
+            return switch;
+        }
+        if let Some(ref mut playback) = self.playback {
+            for recorded in playback.inputs_for_tick(self.sim.tick()) {
+                self.sim
+                    .input(gameworld, recorded.effect.into(), recorded.started);
+            }
+        }
+        self.sim.step(gameworld);
+        for event in self.sim.game_events(gameworld) {
+            gameworld.audio.handle_event(event);
+        }
+        let (position, rotation) = self.player_pose(gameworld);
+        self.trajectory.push(position, rotation);
+        self.update_ghost(gameworld);
+        if self.sim.reached_orbit(gameworld) {
+            let stats = self.sim.stats(gameworld);
+            info!("Player reached orbit: {:?}", stats);
+            self.won = true;
+            gameworld.audio.play_sound(SoundEffect::Victory);
+            self.save_recording(gameworld);
+            self.save_trajectory(gameworld);
+            let new_bests = self.record_run(gameworld, &stats);
+            let best = gameworld
+                .high_scores
+                .get(&self.level_def.name)
+                .cloned()
+                .unwrap_or_default();
+            SceneSwitch::Push(Box::new(victory::VictoryScene::new(stats, best, new_bests)))
+        } else if let Some(reason) = self.sim.failed(gameworld) {
+            let stats = self.sim.stats(gameworld);
+            info!("Player failed: {:?}, {:?}", reason, stats);
+            // Picking restart or quit on the game over screen
+            // leaves a `LevelCommand` just like pausing does.
+            SceneSwitch::Push(Box::new(gameover::GameOverScene::new(reason, stats)))
         } else {
-            scene::SceneSwitch::None
+            SceneSwitch::None
         }
     }
 
     fn draw(&mut self, gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        self.load_graphics(gameworld, ctx)?;
+
         // Focus view on player.
+        let (camera_focus, _) = self.sim.player_position(gameworld);
         let screen_rect = graphics::Rect {
-            x: self.camera_focus.x - CAMERA_WIDTH / 2.0,
-            y: self.camera_focus.y - CAMERA_HEIGHT / 2.0,
+            x: camera_focus.x - CAMERA_WIDTH / 2.0,
+            y: camera_focus.y - CAMERA_HEIGHT / 2.0,
             w: CAMERA_WIDTH,
             h: CAMERA_HEIGHT,
         };
         graphics::set_screen_coordinates(ctx, screen_rect)?;
 
         // Draw background
-        graphics::draw(ctx, &self.background_mesh, ggez::nalgebra::origin(), 0.0)?;
+        if let Some(ref background_mesh) = self.background_mesh {
+            graphics::draw(ctx, background_mesh, ggez::nalgebra::origin(), 0.0)?;
+        }
 
+        let entities = gameworld.specs_world.entities();
         let sprite = gameworld.specs_world.read_storage::<Sprite>();
-        let player = gameworld.specs_world.read_storage::<Player>();
+        let ghost = gameworld.specs_world.read_storage::<Ghost>();
         let mesh = gameworld.specs_world.read_storage::<Mesh>();
         let collider = gameworld.specs_world.read_storage::<Collider>();
         let ncollide_world = gameworld.specs_world.read_resource::<CollisionWorld>();
-        for (c, _, player) in (&collider, &sprite, &player).join() {
-            let (pos, angle) = ggez_collision_object_position(&*ncollide_world, c);
-            let corrected_pos = pos; // - graphics::Vector2::new(8.0, 16.0);
-            // BUGGO: Behold, the shittiest animation timing known to man or god
-            graphics::draw_ex(
-                ctx,
-                &(self.sprites[(self.sprite_idx / 10) % self.sprites.len()].borrow().0),
-                graphics::DrawParam {
-                    dest: corrected_pos,
-                    rotation: angle - player.tumbling_timer,
-                    offset: graphics::Point2::new(0.5, 0.5),
-                    ..graphics::DrawParam::default()
-                },
-            )?;
+        for (entity, sprite) in (&*entities, &sprite).join() {
+            let pose = if let Some(c) = collider.get(entity) {
+                ggez_collision_object_position(&*ncollide_world, c)
+            } else if let Some(g) = ghost.get(entity) {
+                Some((graphics::Point2::new(g.position.x, g.position.y), g.rotation))
+            } else {
+                None
+            };
+            let (pos, angle) = match pose {
+                Some(pose) => pose,
+                None => continue,
+            };
+            let param = graphics::DrawParam {
+                dest: pos,
+                rotation: angle + sprite.rotation,
+                offset: graphics::Point2::new(0.5, 0.5),
+                color: Some(graphics::Color::new(1.0, 1.0, 1.0, sprite.alpha)),
+                ..graphics::DrawParam::default()
+            };
+            match self.images.get(&sprite.image) {
+                Some(&Some(SpriteImage::Image(ref image))) => {
+                    graphics::draw_ex(ctx, &(image.borrow().0), param)?;
+                }
+                Some(&Some(SpriteImage::Sheet(ref sheet))) => {
+                    if let Some(frame) = sheet.borrow().frames.get(sprite.frame) {
+                        graphics::draw_ex(ctx, frame, param)?;
+                    }
+                }
+                _ => (),
+            }
         }
 
         for (c, mesh) in (&collider, &mesh).join() {
-            let (pos, angle) = ggez_collision_object_position(&*ncollide_world, c);
+            let (pos, angle) = match ggez_collision_object_position(&*ncollide_world, c) {
+                Some(pose) => pose,
+                None => continue,
+            };
+            let mesh = match mesh.mesh {
+                Some(ref m) => m,
+                None => continue,
+            };
             graphics::draw_ex(
                 ctx,
-                &mesh.mesh,
+                mesh,
                 graphics::DrawParam {
                     dest: pos,
                     rotation: angle,
@@ -545,7 +575,7 @@ impl scene::Scene<World, input::InputEvent> for LevelScene {
         }
 
         let player_storage = gameworld.specs_world.read_storage::<Player>();
-        let player_component = player_storage.get(self.player_entity).expect("No player?");
+        let player_component = player_storage.get(self.sim.player_entity).expect("No player?");
 
         // let text_point = graphics::Point2::new(10.0, 10.0);
         let velocity_point = graphics::Point2::new(10.0, 10.0);
@@ -557,8 +587,36 @@ impl scene::Scene<World, input::InputEvent> for LevelScene {
         //     let t = ggez::graphics::TextCached::new("Not on ground")?;
         //     t.queue(ctx, text_point, text_color);
         // }
-        let t =
-            ggez::graphics::TextCached::new(format!("Velocity: {:0.1}", player_component.velocity))?;
+        let steering = if player_component.control_mode == ControlMode::Steered {
+            let keys = &gameworld.settings.keys;
+            format!(
+                "\n{}/{}: brake/run, {}: fall faster",
+                keys.key_name(input::Action::Left),
+                keys.key_name(input::Action::Right),
+                keys.key_name(input::Action::Down),
+            )
+        } else {
+            String::new()
+        };
+        let mut limits = String::new();
+        {
+            let stats = gameworld.specs_world.read_resource::<RunStats>();
+            if let Some(limit) = self.sim.fail.time_limit {
+                limits += &format!("\nTime left: {:0.1}", (limit - stats.time).max(0.0));
+            }
+            if let Some(max) = self.sim.fail.max_obstacle_hits {
+                limits += &format!("\nHits: {}/{}", stats.obstacles_hit, max);
+            }
+        }
+        let hud = format!(
+            "Velocity: {:0.1}\nSeed: {}{}{}{}",
+            player_component.velocity,
+            gameworld.seed,
+            limits,
+            steering,
+            if self.playback.is_some() { "\nREPLAY" } else { "" }
+        );
+        let t = text(&mut gameworld.assets, ctx, &hud, HUD_TEXT_SIZE)?;
 
         // Ghetto text outline
         let outline_distance = 1.0;
@@ -577,17 +635,37 @@ impl scene::Scene<World, input::InputEvent> for LevelScene {
         "LevelScene"
     }
 
-    fn input(&mut self, gameworld: &mut World, _ev: input::InputEvent, _started: bool) {
-        if gameworld.input.get_button_pressed(input::Button::Menu) {
-            gameworld.quit = true;
+    fn on_enter(&mut self, gameworld: &mut World) {
+        gameworld
+            .audio
+            .play_music(self.level_def.music.as_ref().map(|m| m.as_str()));
+    }
+
+    fn on_exit(&mut self, gameworld: &mut World) {
+        self.release_inputs(gameworld);
+    }
+
+    fn transition(&self) -> Transition {
+        Transition::Fade(0.5)
+    }
+
+    fn input(
+        &mut self,
+        gameworld: &mut World,
+        ev: input::InputEvent,
+        started: bool,
+    ) -> SceneSwitch {
+        if let InputEffect::Button(input::Button::Menu) = ev {
+            // Pausing isn't part of the run, so it doesn't get recorded.
+            if started {
+                return SceneSwitch::Push(Box::new(pause::PauseScene::new()));
+            }
+            return SceneSwitch::None;
         }
-        if let Some(player) = gameworld
-            .specs_world
-            .write_storage::<Player>()
-            .get_mut(self.player_entity)
-        {
-            player.jumping = gameworld.input.get_button_pressed(input::Button::Jump);
-            // player.walk_direction = gameworld.input.get_axis(input::Axis::Horz);
+        if self.playback.is_none() {
+            self.recording.record(self.sim.tick(), ev, started);
+            self.sim.input(gameworld, ev, started);
         }
+        SceneSwitch::None
     }
 }
@@ -0,0 +1,93 @@
+use ggez;
+use ggez::graphics;
+use ggez_goodies::input::InputEffect;
+use input::{self, Action};
+use scenes::*;
+use world::World;
+
+/// Lets the player pick any level of the campaign they've unlocked.
+pub struct LevelSelectScene {
+    selected: usize,
+}
+
+impl LevelSelectScene {
+    pub fn new(gameworld: &World) -> Self {
+        Self {
+            selected: gameworld.campaign.current(),
+        }
+    }
+}
+
+impl Scene for LevelSelectScene {
+    fn update(&mut self, _gameworld: &mut World) -> SceneSwitch {
+        SceneSwitch::None
+    }
+
+    fn draw(&mut self, gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        reset_screen_coordinates(ctx)?;
+        let mut message = String::from("Pick a level:\n\n");
+        for (i, name) in gameworld.campaign.level_names().iter().enumerate() {
+            let cursor = if i == self.selected { ">" } else { " " };
+            let locked = if gameworld.campaign.is_unlocked(i) {
+                ""
+            } else {
+                " (locked)"
+            };
+            message += &format!("{} {}. {}{}\n", cursor, i + 1, name, locked);
+        }
+        let keys = &gameworld.settings.keys;
+        message += &format!(
+            "\n\n{}/{} to choose, {} to play, {} to go back.",
+            keys.key_name(Action::Up),
+            keys.key_name(Action::Down),
+            keys.key_name(Action::Jump),
+            keys.key_name(Action::Menu),
+        );
+        let t = text(&mut gameworld.assets, ctx, &message, MENU_TEXT_SIZE)?;
+
+        t.queue(ctx, graphics::Point2::new(200.0, 100.0), Some(graphics::WHITE));
+
+        graphics::TextCached::draw_queued(ctx, graphics::DrawParam::default())?;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "LevelSelectScene"
+    }
+
+    fn transition(&self) -> Transition {
+        Transition::Slide(0.25)
+    }
+
+    fn input(
+        &mut self,
+        gameworld: &mut World,
+        ev: input::InputEvent,
+        started: bool,
+    ) -> SceneSwitch {
+        if !started {
+            return SceneSwitch::None;
+        }
+        match ev {
+            InputEffect::Axis(input::Axis::Vert, true) if self.selected > 0 => {
+                self.selected -= 1;
+            }
+            InputEffect::Axis(input::Axis::Vert, false)
+                if self.selected + 1 < gameworld.campaign.len() =>
+            {
+                self.selected += 1;
+            }
+            InputEffect::Button(input::Button::Jump) => {
+                if gameworld.campaign.is_unlocked(self.selected) {
+                    match level::LevelScene::from_campaign(gameworld, self.selected) {
+                        Ok(scene) => return SceneSwitch::Replace(Box::new(scene)),
+                        Err(e) => error!("Could not start level: {}", e),
+                    }
+                }
+            }
+            InputEffect::Button(input::Button::Menu) => return SceneSwitch::Pop,
+            _ => (),
+        }
+        SceneSwitch::None
+    }
+}
@@ -0,0 +1,397 @@
+//! Our own scene stack, instead of ggez-goodies'.
+//!
+//! It works much the same way, but scenes can switch from `input()`
+//! as well as `update()` (see
+//! https://github.com/ggez/ggez-goodies/issues/11), get told when
+//! they come and go, can keep the scene underneath them drawing, and
+//! can fade or slide in.
+
+use ggez;
+use ggez::graphics;
+
+use input;
+use world::{DeltaTime, World};
+
+/// What a scene wants to happen to the stack.
+pub enum SceneSwitch {
+    None,
+    Push(Box<Scene>),
+    Replace(Box<Scene>),
+    Pop,
+}
+
+/// How a scene shows up on screen when it's pushed or replaces
+/// another, or goes away when it's popped.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Transition {
+    /// Just switch.
+    Cut,
+    /// Fade in from black over this many seconds.
+    Fade(f32),
+    /// A black panel slides off to the right over this many seconds.
+    Slide(f32),
+}
+
+impl Transition {
+    fn duration(self) -> f32 {
+        match self {
+            Transition::Cut => 0.0,
+            Transition::Fade(t) | Transition::Slide(t) => t,
+        }
+    }
+
+    /// Draws this transition `progress` of the way through, 0 to 1,
+    /// over whatever's already been drawn.
+    fn draw(self, ctx: &mut ggez::Context, progress: f32) -> ggez::GameResult<()> {
+        // Whatever coordinates the scene left us in, this is all of the screen.
+        let screen = graphics::get_screen_coordinates(ctx);
+        let (rect, alpha) = match self {
+            Transition::Cut => return Ok(()),
+            Transition::Fade(_) => (screen, 1.0 - progress),
+            Transition::Slide(_) => (
+                graphics::Rect::new(screen.x + screen.w * progress, screen.y, screen.w, screen.h),
+                1.0,
+            ),
+        };
+        graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, alpha))?;
+        graphics::rectangle(ctx, graphics::DrawMode::Fill, rect)?;
+        graphics::set_color(ctx, graphics::WHITE)
+    }
+}
+
+pub trait Scene {
+    fn update(&mut self, gameworld: &mut World) -> SceneSwitch;
+    fn draw(&mut self, gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()>;
+    fn input(
+        &mut self,
+        gameworld: &mut World,
+        ev: input::InputEvent,
+        started: bool,
+    ) -> SceneSwitch;
+    fn name(&self) -> &str;
+
+    /// Whether the scene underneath this one gets drawn first.
+    fn draw_previous(&self) -> bool {
+        false
+    }
+
+    /// Called when this becomes the top scene, either by being
+    /// pushed or by whatever was on top of it being popped.
+    fn on_enter(&mut self, _gameworld: &mut World) {}
+
+    /// Called when this stops being the top scene, either by being
+    /// popped or replaced or by having something pushed on top of it.
+    fn on_exit(&mut self, _gameworld: &mut World) {}
+
+    /// How this scene comes in, and goes out again.
+    fn transition(&self) -> Transition {
+        Transition::Cut
+    }
+}
+
+pub struct SceneManager {
+    pub world: World,
+    scenes: Vec<Box<Scene>>,
+    /// The transition being drawn, and how far into it we are in seconds.
+    transition: Option<(Transition, f32)>,
+}
+
+impl SceneManager {
+    pub fn new(world: World) -> Self {
+        Self {
+            world,
+            scenes: Vec::new(),
+            transition: None,
+        }
+    }
+
+    pub fn push(&mut self, scene: Box<Scene>) {
+        self.switch(SceneSwitch::Push(scene));
+    }
+
+    fn exit_current(&mut self) {
+        if let Some(scene) = self.scenes.last_mut() {
+            scene.on_exit(&mut self.world);
+        }
+    }
+
+    fn enter_current(&mut self) {
+        if let Some(scene) = self.scenes.last_mut() {
+            scene.on_enter(&mut self.world);
+        }
+    }
+
+    fn switch(&mut self, switch: SceneSwitch) {
+        let transition = match switch {
+            SceneSwitch::None => return,
+            SceneSwitch::Push(scene) => {
+                self.exit_current();
+                let transition = scene.transition();
+                self.scenes.push(scene);
+                self.enter_current();
+                transition
+            }
+            SceneSwitch::Replace(scene) => {
+                self.exit_current();
+                self.scenes.pop();
+                let transition = scene.transition();
+                self.scenes.push(scene);
+                self.enter_current();
+                transition
+            }
+            SceneSwitch::Pop => {
+                self.exit_current();
+                let transition = self
+                    .scenes
+                    .pop()
+                    .map(|scene| scene.transition())
+                    .unwrap_or(Transition::Cut);
+                if self.scenes.is_empty() {
+                    info!("Last scene popped, quitting");
+                    self.world.quit = true;
+                }
+                self.enter_current();
+                transition
+            }
+        };
+        self.transition = if transition.duration() > 0.0 {
+            Some((transition, 0.0))
+        } else {
+            None
+        };
+    }
+
+    pub fn update(&mut self) {
+        if let Some((transition, elapsed)) = self.transition {
+            let elapsed = elapsed + self.world.specs_world.read_resource::<DeltaTime>().0;
+            self.transition = if elapsed < transition.duration() {
+                Some((transition, elapsed))
+            } else {
+                None
+            };
+        }
+        let switch = match self.scenes.last_mut() {
+            Some(scene) => scene.update(&mut self.world),
+            None => SceneSwitch::None,
+        };
+        self.switch(switch);
+    }
+
+    /// Returns where in the stack drawing starts: the top scene, and
+    /// whatever's under it for as long as each asks for the one below.
+    fn first_drawn(scenes: &[Box<Scene>]) -> usize {
+        let mut first = scenes.len().saturating_sub(1);
+        while first > 0 && scenes[first].draw_previous() {
+            first -= 1;
+        }
+        first
+    }
+
+    pub fn draw(&mut self, ctx: &mut ggez::Context) {
+        let first = Self::first_drawn(&self.scenes);
+        for scene in &mut self.scenes[first..] {
+            if let Err(e) = scene.draw(&mut self.world, ctx) {
+                error!("Error drawing {}: {}", scene.name(), e);
+            }
+        }
+        if let Some((transition, elapsed)) = self.transition {
+            let progress = elapsed / transition.duration();
+            if let Err(e) = transition.draw(ctx, progress) {
+                error!("Error drawing scene transition: {}", e);
+            }
+        }
+    }
+
+    pub fn input(&mut self, ev: input::InputEvent, started: bool) {
+        let switch = match self.scenes.last_mut() {
+            Some(scene) => scene.input(&mut self.world, ev, started),
+            None => SceneSwitch::None,
+        };
+        self.switch(switch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ggez_goodies::input::InputEffect;
+
+    use std::cell::RefCell;
+    use std::path;
+    use std::rc::Rc;
+
+    type Log = Rc<RefCell<Vec<String>>>;
+
+    /// A scene that writes down everything that happens to it.
+    struct Stub {
+        name: &'static str,
+        log: Log,
+        draw_previous: bool,
+        transition: Transition,
+    }
+
+    impl Stub {
+        fn new(name: &'static str, log: &Log) -> Box<Scene> {
+            Box::new(Stub {
+                name,
+                log: log.clone(),
+                draw_previous: false,
+                transition: Transition::Cut,
+            })
+        }
+
+        fn over(name: &'static str, log: &Log) -> Box<Scene> {
+            Box::new(Stub {
+                name,
+                log: log.clone(),
+                draw_previous: true,
+                transition: Transition::Cut,
+            })
+        }
+
+        fn note(&self, what: &str) {
+            self.log
+                .borrow_mut()
+                .push(format!("{} {}", self.name, what));
+        }
+    }
+
+    impl Scene for Stub {
+        fn update(&mut self, _gameworld: &mut World) -> SceneSwitch {
+            self.note("update");
+            SceneSwitch::None
+        }
+
+        fn draw(
+            &mut self,
+            _gameworld: &mut World,
+            _ctx: &mut ggez::Context,
+        ) -> ggez::GameResult<()> {
+            Ok(())
+        }
+
+        /// Pops itself on any input.
+        fn input(
+            &mut self,
+            _gameworld: &mut World,
+            _ev: input::InputEvent,
+            _started: bool,
+        ) -> SceneSwitch {
+            self.note("input");
+            SceneSwitch::Pop
+        }
+
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn draw_previous(&self) -> bool {
+            self.draw_previous
+        }
+
+        fn on_enter(&mut self, _gameworld: &mut World) {
+            self.note("enter");
+        }
+
+        fn on_exit(&mut self, _gameworld: &mut World) {
+            self.note("exit");
+        }
+
+        fn transition(&self) -> Transition {
+            self.transition
+        }
+    }
+
+    fn manager() -> (SceneManager, Log) {
+        let resources = path::Path::new(env!("CARGO_MANIFEST_DIR")).join("resources");
+        let manager = SceneManager::new(World::with_resource_dir(resources));
+        (manager, Rc::new(RefCell::new(Vec::new())))
+    }
+
+    /// Returns what's been logged since last time.
+    fn take(log: &Log) -> Vec<String> {
+        log.borrow_mut().drain(..).collect()
+    }
+
+    #[test]
+    fn scenes_enter_and_exit_in_order() {
+        let (mut manager, log) = manager();
+        manager.push(Stub::new("a", &log));
+        assert_eq!(take(&log), ["a enter"]);
+        manager.switch(SceneSwitch::Push(Stub::new("b", &log)));
+        assert_eq!(take(&log), ["a exit", "b enter"]);
+        manager.switch(SceneSwitch::Replace(Stub::new("c", &log)));
+        assert_eq!(take(&log), ["b exit", "c enter"]);
+        manager.switch(SceneSwitch::Pop);
+        assert_eq!(take(&log), ["c exit", "a enter"]);
+        assert!(!manager.world.quit);
+        manager.switch(SceneSwitch::Pop);
+        assert_eq!(take(&log), ["a exit"]);
+        assert!(manager.world.quit);
+    }
+
+    #[test]
+    fn only_the_top_scene_hears_about_things() {
+        let (mut manager, log) = manager();
+        manager.push(Stub::new("a", &log));
+        manager.push(Stub::over("b", &log));
+        take(&log);
+
+        manager.update();
+        assert_eq!(take(&log), ["b update"]);
+        manager.input(InputEffect::Button(input::Button::Jump), true);
+        assert_eq!(take(&log), ["b input", "b exit", "a enter"]);
+        manager.update();
+        assert_eq!(take(&log), ["a update"]);
+    }
+
+    #[test]
+    fn drawing_goes_down_as_far_as_asked() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let first_drawn = |scenes: Vec<Box<Scene>>| SceneManager::first_drawn(&scenes);
+        assert_eq!(first_drawn(vec![]), 0);
+        assert_eq!(first_drawn(vec![Stub::over("a", &log)]), 0);
+        assert_eq!(
+            first_drawn(vec![Stub::new("a", &log), Stub::new("b", &log)]),
+            1
+        );
+        assert_eq!(
+            first_drawn(vec![
+                Stub::new("a", &log),
+                Stub::new("b", &log),
+                Stub::over("c", &log),
+            ]),
+            1
+        );
+        assert_eq!(
+            first_drawn(vec![
+                Stub::new("a", &log),
+                Stub::over("b", &log),
+                Stub::over("c", &log),
+            ]),
+            0
+        );
+    }
+
+    #[test]
+    fn transitions_run_on_delta_time() {
+        let (mut manager, log) = manager();
+        manager.world.specs_world.add_resource(DeltaTime(0.25));
+        manager.push(Box::new(Stub {
+            name: "a",
+            log: log.clone(),
+            draw_previous: false,
+            transition: Transition::Fade(0.5),
+        }));
+        assert_eq!(manager.transition, Some((Transition::Fade(0.5), 0.0)));
+        manager.update();
+        assert_eq!(manager.transition, Some((Transition::Fade(0.5), 0.25)));
+        manager.update();
+        assert_eq!(manager.transition, None);
+
+        // Cuts don't have anything to draw.
+        manager.push(Stub::new("b", &log));
+        assert_eq!(manager.transition, None);
+    }
+}
@@ -1,58 +1,79 @@
 use ggez;
 use ggez::graphics;
-use ggez_goodies::scene;
-use input;
+use ggez_goodies::input::InputEffect;
+use input::{self, Action};
 use scenes::*;
 use world::World;
 
-
-pub struct MenuScene {
-    done: bool,
-}
+pub struct MenuScene;
 
 impl MenuScene {
     pub fn new() -> Self {
-        Self {
-            done: false,
+        MenuScene
+    }
+
+    /// A line for each level the player has beaten, with their bests.
+    fn best_runs(gameworld: &World) -> String {
+        let mut lines = String::new();
+        for name in gameworld.campaign.level_names() {
+            let best = match gameworld.high_scores.get(&name) {
+                Some(best) => best,
+                None => continue,
+            };
+            lines += &format!(
+                "{}: {}, {} peak velocity, {} hits\n",
+                name,
+                best.time.map_or(String::from("-"), |t| format!("{:0.1}s", t)),
+                best.peak_velocity.map_or(String::from("-"), |v| format!("{:0.0}", v)),
+                best.fewest_hits.map_or(String::from("-"), |h| h.to_string()),
+            );
+        }
+        if lines.is_empty() {
+            lines += "None yet!\n";
         }
+        lines
     }
 }
 
-
-impl scene::Scene<World, input::InputEvent> for MenuScene {
-    fn update(&mut self, gameworld: &mut World) -> FSceneSwitch {
-        if self.done {
-            // See https://github.com/ggez/ggez-goodies/issues/11
-            // We work around that...
-            scene::SceneSwitch::Pop
-        } else {
-            scene::SceneSwitch::None
-        }
+impl Scene for MenuScene {
+    fn update(&mut self, _gameworld: &mut World) -> SceneSwitch {
+        SceneSwitch::None
     }
 
-    fn draw(&mut self, _gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
-        let t =
-            ggez::graphics::TextCached::new(r#"
+    fn draw(&mut self, gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        reset_screen_coordinates(ctx)?;
+        let keys = &gameworld.settings.keys;
+        let message = format!(
+            r#"
 Running In To Space
 
 
 You are an astronaut trapped on a remote planet and need to
 run your way into orbit!
 
-Not actually done, but close!  There's no win condition
-detection, and there were supposed to be more levels and
-different hazards and such.
+Get going fast enough and high enough to make it into
+orbit and you win!  Each planet you escape unlocks the next.
 
 
 
-Directions: You will start running, just press Z to jump
+Directions: You will start running, just press {jump} to jump
 over obstacles.
-Escape key quits.
+{menu} quits.
 
 
+Press {jump} to begin!
+Press {down} to pick a level.
+Press {right} to change the settings.
 
-Press Z to begin!
-"#)?;
+Best runs:
+{best}"#,
+            jump = keys.key_name(Action::Jump),
+            menu = keys.key_name(Action::Menu),
+            down = keys.key_name(Action::Down),
+            right = keys.key_name(Action::Right),
+            best = Self::best_runs(gameworld),
+        );
+        let t = text(&mut gameworld.assets, ctx, &message, MENU_TEXT_SIZE)?;
 
         t.queue(ctx, graphics::Point2::new(200.0, 100.0), Some(graphics::WHITE));
 
@@ -64,9 +85,42 @@ Press Z to begin!
         "MenuScene"
     }
 
-    fn input(&mut self, gameworld: &mut World, _ev: input::InputEvent, _started: bool) {
-        if gameworld.input.get_button_pressed(input::Button::Jump) {
-            self.done = true;
+    fn on_enter(&mut self, gameworld: &mut World) {
+        // The title's quiet, however we got back to it.
+        gameworld.audio.play_music(None);
+    }
+
+    fn input(
+        &mut self,
+        gameworld: &mut World,
+        ev: input::InputEvent,
+        started: bool,
+    ) -> SceneSwitch {
+        if !started {
+            return SceneSwitch::None;
+        }
+        match ev {
+            InputEffect::Button(input::Button::Jump) => {
+                let current = gameworld.campaign.current();
+                match level::LevelScene::from_campaign(gameworld, current) {
+                    Ok(scene) => SceneSwitch::Push(Box::new(scene)),
+                    Err(e) => {
+                        error!("Could not start level: {}", e);
+                        SceneSwitch::None
+                    }
+                }
+            }
+            InputEffect::Axis(input::Axis::Vert, false) => {
+                SceneSwitch::Push(Box::new(levelselect::LevelSelectScene::new(gameworld)))
+            }
+            InputEffect::Axis(input::Axis::Horz, true) => {
+                SceneSwitch::Push(Box::new(settings::SettingsScene::new()))
+            }
+            InputEffect::Button(input::Button::Menu) => {
+                gameworld.quit = true;
+                SceneSwitch::None
+            }
+            _ => SceneSwitch::None,
         }
     }
-}
\ No newline at end of file
+}
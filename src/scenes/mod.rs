@@ -1,11 +1,48 @@
-use ggez_goodies::scene;
+use ggez;
+use ggez::graphics;
+
+use warmy;
 
 use input;
+use resources;
 use world::World;
 
+pub mod controls;
+pub mod gameover;
 pub mod level;
+pub mod levelselect;
+pub mod manager;
 pub mod menu;
+pub mod pause;
+pub mod settings;
+pub mod victory;
+
+pub use self::manager::{Scene, SceneManager, SceneSwitch, Transition};
+
+/// The font all our text is in.
+pub const FONT: &str = "/fonts/DejaVuSerif.ttf";
+/// Text sizes, in pixels.
+pub const MENU_TEXT_SIZE: u32 = 16;
+pub const HUD_TEXT_SIZE: u32 = 18;
+
+/// Makes some text in `FONT` at the given size.  Takes the asset store
+/// rather than the whole `World` so it can be used while bits of the
+/// specs world are borrowed.
+pub fn text(
+    store: &mut warmy::Store<ggez::Context>,
+    ctx: &mut ggez::Context,
+    text: &str,
+    size: u32,
+) -> ggez::GameResult<graphics::TextCached> {
+    let font = store
+        .get::<_, resources::Font>(&resources::FontKey::new(FONT, size), ctx)
+        .map_err(|e| ggez::GameError::ResourceLoadError(format!("Could not load font: {}", e)))?;
+    let font = font.borrow();
+    font.text(text)
+}
 
-// Shortcuts for our scene type.
-pub type FSceneSwitch = scene::SceneSwitch<World, input::InputEvent>;
-pub type FSceneStack = scene::SceneStack<World, input::InputEvent>;
+/// The `LevelScene` moves the camera all over the place; scenes that
+/// just draw text and such call this to get plain screen coordinates back.
+pub fn reset_screen_coordinates(ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+    graphics::set_screen_coordinates(ctx, graphics::Rect::new(0.0, 0.0, 800.0, 600.0))
+}
@@ -0,0 +1,118 @@
+use ggez;
+use ggez::graphics;
+use ggez_goodies::input::InputEffect;
+use input::{self, Action};
+use scenes::*;
+use world::{LevelCommand, World};
+
+/// Something on the pause menu.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum PauseChoice {
+    Resume,
+    Settings,
+    /// Goes back to the level and has it do something.
+    Level(LevelCommand),
+}
+
+const CHOICES: [(PauseChoice, &str); 5] = [
+    (PauseChoice::Resume, "Resume"),
+    (PauseChoice::Level(LevelCommand::Restart), "Restart level"),
+    (PauseChoice::Settings, "Settings"),
+    (PauseChoice::Level(LevelCommand::QuitToTitle), "Quit to title"),
+    (PauseChoice::Level(LevelCommand::QuitGame), "Quit game"),
+];
+
+/// Pushed over a `LevelScene` so it stops updating, but still gets
+/// drawn underneath.
+pub struct PauseScene {
+    selected: usize,
+}
+
+impl PauseScene {
+    pub fn new() -> Self {
+        Self { selected: 0 }
+    }
+
+    fn pick(choice: PauseChoice, gameworld: &mut World) -> SceneSwitch {
+        match choice {
+            PauseChoice::Resume => SceneSwitch::Pop,
+            PauseChoice::Settings => SceneSwitch::Push(Box::new(settings::SettingsScene::new())),
+            PauseChoice::Level(command) => {
+                gameworld.level_command = Some(command);
+                SceneSwitch::Pop
+            }
+        }
+    }
+}
+
+impl Scene for PauseScene {
+    fn update(&mut self, _gameworld: &mut World) -> SceneSwitch {
+        SceneSwitch::None
+    }
+
+    fn draw(&mut self, gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        reset_screen_coordinates(ctx)?;
+        graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, 0.6))?;
+        graphics::rectangle(
+            ctx,
+            graphics::DrawMode::Fill,
+            graphics::Rect::new(0.0, 0.0, 800.0, 600.0),
+        )?;
+        graphics::set_color(ctx, graphics::WHITE)?;
+
+        let mut message = String::from("Paused\n\n");
+        for (i, &(_, label)) in CHOICES.iter().enumerate() {
+            let cursor = if i == self.selected { ">" } else { " " };
+            message += &format!("{} {}\n", cursor, label);
+        }
+        let keys = &gameworld.settings.keys;
+        message += &format!(
+            "\n\n{}/{} to choose, {} to pick, {} to resume.",
+            keys.key_name(Action::Up),
+            keys.key_name(Action::Down),
+            keys.key_name(Action::Jump),
+            keys.key_name(Action::Menu),
+        );
+        let t = text(&mut gameworld.assets, ctx, &message, MENU_TEXT_SIZE)?;
+
+        t.queue(ctx, graphics::Point2::new(300.0, 200.0), Some(graphics::WHITE));
+
+        graphics::TextCached::draw_queued(ctx, graphics::DrawParam::default())?;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "PauseScene"
+    }
+
+    fn draw_previous(&self) -> bool {
+        true
+    }
+
+    fn input(
+        &mut self,
+        gameworld: &mut World,
+        ev: input::InputEvent,
+        started: bool,
+    ) -> SceneSwitch {
+        if !started {
+            return SceneSwitch::None;
+        }
+        match ev {
+            InputEffect::Axis(input::Axis::Vert, true) if self.selected > 0 => {
+                self.selected -= 1;
+            }
+            InputEffect::Axis(input::Axis::Vert, false) if self.selected + 1 < CHOICES.len() => {
+                self.selected += 1;
+            }
+            InputEffect::Button(input::Button::Jump) => {
+                return Self::pick(CHOICES[self.selected].0, gameworld);
+            }
+            InputEffect::Button(input::Button::Menu) => {
+                return Self::pick(PauseChoice::Resume, gameworld);
+            }
+            _ => (),
+        }
+        SceneSwitch::None
+    }
+}
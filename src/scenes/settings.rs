@@ -0,0 +1,184 @@
+use ggez;
+use ggez::graphics;
+use ggez_goodies::input::InputEffect;
+use input::{self, Action};
+use scenes::*;
+use settings::RESOLUTIONS;
+use world::World;
+
+/// How much one press changes a volume by.
+const VOLUME_STEP: f32 = 0.1;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Row {
+    Resolution,
+    Fullscreen,
+    Vsync,
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    Ghost,
+    Controls,
+    Back,
+}
+
+const ROWS: [Row; 9] = [
+    Row::Resolution,
+    Row::Fullscreen,
+    Row::Vsync,
+    Row::MasterVolume,
+    Row::MusicVolume,
+    Row::SfxVolume,
+    Row::Ghost,
+    Row::Controls,
+    Row::Back,
+];
+
+/// Lets the player change and save their `Settings`.  Every change
+/// takes effect and gets saved straight away.
+pub struct SettingsScene {
+    selected: usize,
+    /// Set when something about the window changes; that needs a
+    /// `Context`, so it happens next time we're drawn.
+    window_changed: bool,
+}
+
+impl SettingsScene {
+    pub fn new() -> Self {
+        Self {
+            selected: 0,
+            window_changed: false,
+        }
+    }
+
+    fn describe(row: Row, gameworld: &World) -> String {
+        let settings = &gameworld.settings;
+        let on_off = |b: bool| if b { "on" } else { "off" };
+        let percent = |v: f32| format!("{:0.0}%", v * 100.0);
+        match row {
+            Row::Resolution => format!(
+                "Resolution: {}x{}",
+                settings.resolution.0, settings.resolution.1
+            ),
+            Row::Fullscreen => format!("Fullscreen: {}", on_off(settings.fullscreen)),
+            Row::Vsync => format!("Vsync: {}", on_off(settings.vsync)),
+            Row::MasterVolume => format!("Master volume: {}", percent(settings.volumes.master)),
+            Row::MusicVolume => format!("Music volume: {}", percent(settings.volumes.music)),
+            Row::SfxVolume => format!("Sound effect volume: {}", percent(settings.volumes.sfx)),
+            Row::Ghost => format!("Race the best run's ghost: {}", on_off(settings.show_ghost)),
+            Row::Controls => String::from("Controls..."),
+            Row::Back => String::from("Back"),
+        }
+    }
+
+    /// Changes a setting; `forward` is whether it's going up or back.
+    /// Returns whether anything changed.
+    fn change(&mut self, row: Row, forward: bool, gameworld: &mut World) -> bool {
+        let settings = &mut gameworld.settings;
+        let step = if forward { VOLUME_STEP } else { -VOLUME_STEP };
+        let nudge = |v: &mut f32| *v = ((*v + step) * 10.0).round().max(0.0).min(10.0) / 10.0;
+        match row {
+            Row::Resolution => {
+                let count = RESOLUTIONS.len();
+                let index = settings.resolution_index();
+                let index = if forward {
+                    (index + 1) % count
+                } else {
+                    (index + count - 1) % count
+                };
+                settings.resolution = RESOLUTIONS[index];
+                self.window_changed = true;
+            }
+            Row::Fullscreen => {
+                settings.fullscreen = !settings.fullscreen;
+                self.window_changed = true;
+            }
+            Row::Vsync => {
+                settings.vsync = !settings.vsync;
+                self.window_changed = true;
+            }
+            Row::MasterVolume => nudge(&mut settings.volumes.master),
+            Row::MusicVolume => nudge(&mut settings.volumes.music),
+            Row::SfxVolume => nudge(&mut settings.volumes.sfx),
+            Row::Ghost => settings.show_ghost = !settings.show_ghost,
+            Row::Controls | Row::Back => return false,
+        }
+        true
+    }
+}
+
+impl Scene for SettingsScene {
+    fn update(&mut self, _gameworld: &mut World) -> SceneSwitch {
+        SceneSwitch::None
+    }
+
+    fn draw(&mut self, gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        if self.window_changed {
+            self.window_changed = false;
+            if let Err(e) = gameworld.settings.apply_window(ctx) {
+                error!("Could not change the window: {}", e);
+            }
+        }
+        reset_screen_coordinates(ctx)?;
+        let mut message = String::from("Settings:\n\n");
+        for (i, row) in ROWS.iter().enumerate() {
+            let cursor = if i == self.selected { ">" } else { " " };
+            message += &format!("{} {}\n", cursor, Self::describe(*row, gameworld));
+        }
+        let keys = &gameworld.settings.keys;
+        message += &format!(
+            "\n\n{}/{} to choose, {}/{} to change, {} to go back.",
+            keys.key_name(Action::Up),
+            keys.key_name(Action::Down),
+            keys.key_name(Action::Left),
+            keys.key_name(Action::Right),
+            keys.key_name(Action::Menu),
+        );
+        let t = text(&mut gameworld.assets, ctx, &message, MENU_TEXT_SIZE)?;
+
+        t.queue(ctx, graphics::Point2::new(200.0, 100.0), Some(graphics::WHITE));
+
+        graphics::TextCached::draw_queued(ctx, graphics::DrawParam::default())?;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "SettingsScene"
+    }
+
+    fn input(
+        &mut self,
+        gameworld: &mut World,
+        ev: input::InputEvent,
+        started: bool,
+    ) -> SceneSwitch {
+        if !started {
+            return SceneSwitch::None;
+        }
+        let row = ROWS[self.selected];
+        let changed = match ev {
+            InputEffect::Axis(input::Axis::Vert, true) => {
+                self.selected = self.selected.saturating_sub(1);
+                false
+            }
+            InputEffect::Axis(input::Axis::Vert, false) => {
+                self.selected = (self.selected + 1).min(ROWS.len() - 1);
+                false
+            }
+            InputEffect::Axis(input::Axis::Horz, forward) => self.change(row, forward, gameworld),
+            InputEffect::Button(input::Button::Jump) => match row {
+                Row::Controls => {
+                    return SceneSwitch::Push(Box::new(controls::ControlsScene::new()))
+                }
+                Row::Back => return SceneSwitch::Pop,
+                row => self.change(row, true, gameworld),
+            },
+            InputEffect::Button(input::Button::Menu) => return SceneSwitch::Pop,
+        };
+        if changed {
+            gameworld.apply_settings();
+            gameworld.save_settings();
+        }
+        SceneSwitch::None
+    }
+}
@@ -0,0 +1,111 @@
+use ggez;
+use ggez::graphics;
+use ggez_goodies::input::InputEffect;
+use input::{self, Action};
+use scenes::*;
+use scores::{BestScores, NewBests};
+use world::{LevelCommand, RunStats, World};
+
+/// Shown when the player makes it into orbit.
+/// Pops back to the `LevelScene`, which moves on to the next level,
+/// or back to the title if we leave it `LevelCommand::QuitToTitle`.
+pub struct VictoryScene {
+    stats: RunStats,
+    /// The level's bests, including this run.
+    best: BestScores,
+    new_bests: NewBests,
+}
+
+impl VictoryScene {
+    pub fn new(stats: RunStats, best: BestScores, new_bests: NewBests) -> Self {
+        Self {
+            stats,
+            best,
+            new_bests,
+        }
+    }
+}
+
+/// Tacked onto a stat that's a new best.
+fn new_best(new: bool) -> &'static str {
+    if new {
+        "  New best!"
+    } else {
+        ""
+    }
+}
+
+impl Scene for VictoryScene {
+    fn update(&mut self, _gameworld: &mut World) -> SceneSwitch {
+        SceneSwitch::None
+    }
+
+    fn draw(&mut self, gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        reset_screen_coordinates(ctx)?;
+
+        let best = &self.best;
+        let keys = &gameworld.settings.keys;
+        let message = format!(
+            r#"
+You made it into space!
+
+
+Time: {:0.1} seconds{}
+Peak velocity: {:0.0}{}
+Obstacles hit: {}{}
+
+Best time: {}
+Best peak velocity: {}
+Fewest obstacles hit: {}
+
+
+Press {} to continue.
+Press {} to go back to the title.
+"#,
+            self.stats.time,
+            new_best(self.new_bests.time),
+            self.stats.peak_velocity,
+            new_best(self.new_bests.peak_velocity),
+            self.stats.obstacles_hit,
+            new_best(self.new_bests.fewest_hits),
+            best.time.map_or(String::from("-"), |t| format!("{:0.1} seconds", t)),
+            best.peak_velocity.map_or(String::from("-"), |v| format!("{:0.0}", v)),
+            best.fewest_hits.map_or(String::from("-"), |h| h.to_string()),
+            keys.key_name(Action::Jump),
+            keys.key_name(Action::Menu),
+        );
+        let t = text(&mut gameworld.assets, ctx, &message, MENU_TEXT_SIZE)?;
+
+        t.queue(ctx, graphics::Point2::new(200.0, 100.0), Some(graphics::WHITE));
+
+        graphics::TextCached::draw_queued(ctx, graphics::DrawParam::default())?;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "VictoryScene"
+    }
+
+    fn transition(&self) -> Transition {
+        Transition::Fade(0.5)
+    }
+
+    fn input(
+        &mut self,
+        gameworld: &mut World,
+        ev: input::InputEvent,
+        started: bool,
+    ) -> SceneSwitch {
+        if !started {
+            return SceneSwitch::None;
+        }
+        match ev {
+            InputEffect::Button(input::Button::Jump) => SceneSwitch::Pop,
+            InputEffect::Button(input::Button::Menu) => {
+                gameworld.level_command = Some(LevelCommand::QuitToTitle);
+                SceneSwitch::Pop
+            }
+            _ => SceneSwitch::None,
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! The player's best results on each level, saved between runs.
+//!
+//! The file has a version number so we can still read old ones if
+//! what we save changes.  If it's broken, or from a newer version of
+//! the game, it gets moved out of the way and we start afresh rather
+//! than refusing to start.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path;
+
+use error::Err;
+use util;
+use world::RunStats;
+
+/// What version of the file we write.  If the format changes, bump
+/// this and teach `HighScores::migrate()` to read the old one.
+const CURRENT_VERSION: u32 = 1;
+
+const FILE_NAME: &str = "scores.ron";
+
+/// The best of each stat on one level, from any run; they don't all
+/// have to come from the same one.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BestScores {
+    /// Fastest time to orbit, in seconds.
+    pub time: Option<f32>,
+    pub peak_velocity: Option<f32>,
+    pub fewest_hits: Option<u32>,
+}
+
+/// Which stats a run just beat.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct NewBests {
+    pub time: bool,
+    pub peak_velocity: bool,
+    pub fewest_hits: bool,
+}
+
+impl BestScores {
+    fn record(&mut self, stats: &RunStats) -> NewBests {
+        let new = NewBests {
+            time: self.time.map_or(true, |t| stats.time < t),
+            peak_velocity: self.peak_velocity.map_or(true, |v| stats.peak_velocity > v),
+            fewest_hits: self.fewest_hits.map_or(true, |h| stats.obstacles_hit < h),
+        };
+        if new.time {
+            self.time = Some(stats.time);
+        }
+        if new.peak_velocity {
+            self.peak_velocity = Some(stats.peak_velocity);
+        }
+        if new.fewest_hits {
+            self.fewest_hits = Some(stats.obstacles_hit);
+        }
+        new
+    }
+
+    /// Whether these could have come from a real run.
+    fn is_valid(&self) -> bool {
+        let ok = |v: Option<f32>| v.map_or(true, |v| v.is_finite() && v >= 0.0);
+        ok(self.time) && ok(self.peak_velocity)
+    }
+}
+
+/// What the save file looks like.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct ScoresFile {
+    version: u32,
+    levels: BTreeMap<String, BestScores>,
+}
+
+/// The best scores for every level, by `LevelDef::name`.
+#[derive(Clone, Debug, Default)]
+pub struct HighScores {
+    levels: BTreeMap<String, BestScores>,
+}
+
+impl HighScores {
+    /// Loads the saved scores.  If there are none we start with an
+    /// empty table, and if they can't be used we move the file aside
+    /// to `scores.ron.bak` and do the same.
+    pub fn load(dir: &path::Path) -> Self {
+        let path = dir.join(FILE_NAME);
+        if !path.exists() {
+            return Self::default();
+        }
+        match Self::load_from(&path) {
+            Ok(scores) => scores,
+            Err(e) => {
+                error!("Could not load high scores, starting afresh: {}", e);
+                let backup = dir.join(format!("{}.bak", FILE_NAME));
+                if let Err(e) = fs::rename(&path, &backup) {
+                    warn!("Could not move old high scores to {:?}: {}", backup, e);
+                }
+                Self::default()
+            }
+        }
+    }
+
+    fn load_from(path: &path::Path) -> Result<Self, Err> {
+        let scores: ScoresFile = util::load_ron(path)?;
+        let mut scores = Self::migrate(scores)?;
+        scores.levels.retain(|level, best| {
+            let valid = best.is_valid();
+            if !valid {
+                warn!("Throwing away bad high scores for {}: {:?}", level, best);
+            }
+            valid
+        });
+        Ok(scores)
+    }
+
+    /// Brings an old file up to date.  There's only been the one
+    /// version so far, so all this does is turn away newer ones.
+    fn migrate(file: ScoresFile) -> Result<Self, Err> {
+        match file.version {
+            CURRENT_VERSION => Ok(Self {
+                levels: file.levels,
+            }),
+            v => Err(Err::SaveError {
+                msg: format!(
+                    "High scores are version {}, but we only know up to {}",
+                    v, CURRENT_VERSION
+                ),
+            }),
+        }
+    }
+
+    pub fn save(&self, dir: &path::Path) -> Result<(), Err> {
+        let file = ScoresFile {
+            version: CURRENT_VERSION,
+            levels: self.levels.clone(),
+        };
+        util::save_ron(&dir.join(FILE_NAME), &file)
+    }
+
+    pub fn get(&self, level: &str) -> Option<&BestScores> {
+        self.levels.get(level)
+    }
+
+    /// Records a finished run, and returns which stats were new bests.
+    pub fn record(&mut self, level: &str, stats: &RunStats) -> NewBests {
+        self.levels
+            .entry(level.to_owned())
+            .or_insert_with(BestScores::default)
+            .record(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(time: f32, peak_velocity: f32, obstacles_hit: u32) -> RunStats {
+        RunStats {
+            time,
+            peak_velocity,
+            obstacles_hit,
+        }
+    }
+
+    /// Writes `contents` as the scores file in a fresh directory.
+    fn dir_with_scores(name: &str, contents: &str) -> path::PathBuf {
+        let dir = util::test_dir(name);
+        fs::write(dir.join(FILE_NAME), contents).expect("Could not write scores file");
+        dir
+    }
+
+    fn assert_fell_back(dir: &path::Path) {
+        let scores = HighScores::load(dir);
+        assert!(scores.levels.is_empty());
+        assert!(!dir.join(FILE_NAME).exists());
+        assert!(dir.join(format!("{}.bak", FILE_NAME)).exists());
+    }
+
+    #[test]
+    fn scores_round_trip() {
+        let dir = util::test_dir("scores-round-trip");
+        let mut scores = HighScores::default();
+        scores.record("Level 1", &run(30.0, 5.0, 2));
+        scores.record("Level 1", &run(40.0, 6.0, 3));
+        scores.record("Level 2", &run(50.0, 4.0, 0));
+        scores.save(&dir).unwrap();
+
+        let loaded = HighScores::load(&dir);
+        assert_eq!(loaded.levels, scores.levels);
+        let best = loaded.get("Level 1").unwrap();
+        assert_eq!(best.fewest_hits, Some(2));
+        assert!(best.peak_velocity.unwrap() > 5.5);
+    }
+
+    #[test]
+    fn garbage_files_get_moved_aside() {
+        let dir = dir_with_scores("scores-garbage", "this is not RON {{{");
+        assert_fell_back(&dir);
+    }
+
+    #[test]
+    fn newer_versions_get_moved_aside() {
+        let dir = dir_with_scores("scores-newer", "(version: 99, levels: {})");
+        assert_fell_back(&dir);
+    }
+
+    #[test]
+    fn bad_scores_get_dropped() {
+        let dir = dir_with_scores(
+            "scores-bad",
+            r#"(version: 1, levels: {
+                "Bad": (time: Some(-1.0)),
+                "Good": (time: Some(10.0))
+            })"#,
+        );
+        let scores = HighScores::load(&dir);
+        assert!(scores.get("Bad").is_none());
+        assert!(scores.get("Good").is_some());
+        assert!(dir.join(FILE_NAME).exists());
+    }
+}
@@ -0,0 +1,211 @@
+//! The player's preferences, saved in the user config directory
+//! between runs.
+
+use ggez::{self, conf, graphics};
+
+use std::path;
+
+use audio::Volumes;
+use error::Err;
+use input::KeyBindings;
+use util;
+
+/// Window sizes we offer.  They're all 4:3 like the 800x600 the
+/// game's drawn at, so nothing gets stretched.
+pub const RESOLUTIONS: [(u32, u32); 4] = [(800, 600), (1024, 768), (1280, 960), (1600, 1200)];
+
+/// Everything the player can change.  Anything missing from the
+/// file gets its default, so old settings files keep working.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub resolution: (u32, u32),
+    pub fullscreen: bool,
+    pub vsync: bool,
+    pub volumes: Volumes,
+    /// Whether to race against a ghost of the best run, if there is one.
+    pub show_ghost: bool,
+    pub keys: KeyBindings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            resolution: RESOLUTIONS[0],
+            fullscreen: false,
+            vsync: true,
+            volumes: Volumes::default(),
+            show_ghost: true,
+            keys: KeyBindings::default(),
+        }
+    }
+}
+
+impl Settings {
+    const FILE_NAME: &'static str = "settings.ron";
+
+    /// Loads the saved settings, or the defaults if there aren't any.
+    /// Doesn't check them; see `fix()`.
+    pub fn load(dir: &path::Path) -> Result<Self, Err> {
+        let path = dir.join(Self::FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let mut settings: Self = util::load_ron(&path)?;
+        settings.keys.fill_defaults();
+        Ok(settings)
+    }
+
+    pub fn save(&self, dir: &path::Path) -> Result<(), Err> {
+        util::save_ron(&dir.join(Self::FILE_NAME), self)
+    }
+
+    /// Puts anything that doesn't make sense back to its default,
+    /// and returns what was wrong, if anything.
+    pub fn fix(&mut self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let defaults = Self::default();
+        if !RESOLUTIONS.contains(&self.resolution) {
+            problems.push(format!(
+                "Resolution {}x{} isn't one we support",
+                self.resolution.0, self.resolution.1
+            ));
+            self.resolution = defaults.resolution;
+        }
+        fix_volume("Master", &mut self.volumes.master, &mut problems);
+        fix_volume("Music", &mut self.volumes.music, &mut problems);
+        fix_volume("Sound effect", &mut self.volumes.sfx, &mut problems);
+        if let Err(e) = self.keys.validate() {
+            problems.push(e.to_string());
+            self.keys = defaults.keys;
+        }
+        problems
+    }
+
+    /// The index of our resolution in `RESOLUTIONS`.
+    pub fn resolution_index(&self) -> usize {
+        RESOLUTIONS
+            .iter()
+            .position(|r| *r == self.resolution)
+            .unwrap_or(0)
+    }
+
+    pub fn window_mode(&self) -> conf::WindowMode {
+        let fullscreen = if self.fullscreen {
+            conf::FullscreenType::Desktop
+        } else {
+            conf::FullscreenType::Off
+        };
+        conf::WindowMode::default()
+            .dimensions(self.resolution.0, self.resolution.1)
+            .fullscreen_type(fullscreen)
+            .vsync(self.vsync)
+    }
+
+    /// Sets up the window the way these settings say.
+    pub fn apply_window(&self, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        graphics::set_mode(ctx, self.window_mode())
+    }
+}
+
+fn fix_volume(name: &str, volume: &mut f32, problems: &mut Vec<String>) {
+    if *volume >= 0.0 && *volume <= 1.0 {
+        return;
+    }
+    problems.push(format!("{} volume {} isn't between 0 and 1", name, volume));
+    *volume = if volume.is_nan() {
+        1.0
+    } else {
+        volume.max(0.0).min(1.0)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ggez::event::Keycode;
+    use std::f32;
+
+    use input::Action;
+
+    #[test]
+    fn defaults_need_no_fixing() {
+        let mut settings = Settings::default();
+        assert!(settings.fix().is_empty());
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn volumes_get_clamped() {
+        let mut settings = Settings::default();
+        settings.volumes = Volumes {
+            master: 1.5,
+            music: -0.5,
+            sfx: 0.25,
+        };
+        assert_eq!(settings.fix().len(), 2);
+        assert_eq!(
+            settings.volumes,
+            Volumes {
+                master: 1.0,
+                music: 0.0,
+                sfx: 0.25,
+            }
+        );
+    }
+
+    #[test]
+    fn nan_volumes_get_reset() {
+        let mut settings = Settings::default();
+        settings.volumes.sfx = f32::NAN;
+        assert_eq!(settings.fix().len(), 1);
+        assert_eq!(
+            settings.volumes,
+            Volumes {
+                sfx: 1.0,
+                ..Volumes::default()
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_resolutions_get_reset() {
+        let mut settings = Settings::default();
+        settings.resolution = (123, 456);
+        settings.fullscreen = true;
+        assert_eq!(settings.fix().len(), 1);
+        assert_eq!(settings.resolution, RESOLUTIONS[0]);
+        // Nothing else gets touched.
+        assert!(settings.fullscreen);
+    }
+
+    #[test]
+    fn bad_bindings_get_reset() {
+        let mut settings = Settings::default();
+        // Jump is already on Z.
+        settings.keys = settings.keys.rebound(Action::Menu, Keycode::Z);
+        assert_eq!(settings.fix().len(), 1);
+        assert_eq!(settings.keys, KeyBindings::default());
+    }
+
+    #[test]
+    fn everything_wrong_gets_reported() {
+        let mut settings = Settings::default();
+        settings.resolution = (1, 1);
+        settings.volumes.master = 2.0;
+        settings.volumes.music = f32::NAN;
+        settings.keys = settings.keys.rebound(Action::Menu, Keycode::Z);
+        assert_eq!(settings.fix().len(), 4);
+        assert_eq!(settings.resolution, RESOLUTIONS[0]);
+        assert_eq!(settings.keys, KeyBindings::default());
+        assert_eq!(
+            settings.volumes,
+            Volumes {
+                master: 1.0,
+                music: 1.0,
+                ..Volumes::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,880 @@
+//! The headless guts of a level: the specs entities, the systems
+//! that run on them, and the hand-rolled player motion.
+//!
+//! Nothing in here touches a `ggez::Context`, so a `Simulation` can be
+//! built and stepped without a window, from tests or whatever.
+//! `LevelScene` wraps one of these and adds drawing on top.
+
+use ggez_goodies::input::InputEffect;
+use nalgebra as na;
+use ncollide2d as nc;
+use specs::{self, Builder};
+
+use std::f32;
+
+use components::*;
+use error::Err;
+use events::{CollisionEvent, GameEvent};
+use input;
+use layers::Layer;
+use level::{FailDef, LevelDef, ObstacleDef, PlanetDef, PlayerDef};
+use procgen;
+use shrev::{EventChannel, ReaderId};
+use systems::*;
+use util::*;
+use world::{DeltaTime, GameRng, Integrator, RunStats, World};
+
+/// The astronaut's running animation, for the player and their ghost.
+const ASTRONAUT_SHEET: &str = "/images/astromonaut.ase";
+
+fn astronaut_sprite() -> (Sprite, Animation) {
+    (
+        Sprite::new(ASTRONAUT_SHEET),
+        Animation::from_sheet(ASTRONAUT_SHEET, None, LoopMode::Loop),
+    )
+}
+
+/// How high above the ground the lowest point of the player's
+/// orbit has to be before we call it a stable orbit.
+const ORBIT_CLEARANCE: f32 = 100.0;
+/// How many planet radii away from the planet the player has to
+/// get before we say they've escaped it entirely.
+const ESCAPE_RADII: f32 = 5.0;
+
+/// Why the player lost.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FailReason {
+    OutOfTime,
+    TooManyHits,
+}
+
+impl FailReason {
+    pub fn description(self) -> &'static str {
+        match self {
+            FailReason::OutOfTime => "You ran out of time.",
+            FailReason::TooManyHits => "You hit too much junk.",
+        }
+    }
+}
+
+pub struct Simulation {
+    dispatcher: specs::Dispatcher<'static, 'static>,
+    pub player_entity: specs::Entity,
+    /// The planet the player starts on, which they have to get into orbit around.
+    pub planet_entity: specs::Entity,
+    planet_radius: f32,
+    /// Everything we've created, so we can clean it all up again.
+    entities: Vec<specs::Entity>,
+    /// How many times we've been stepped.
+    tick: u64,
+    game_events: ReaderId<GameEvent>,
+    pub fail: FailDef,
+}
+
+impl Simulation {
+    /// Builds the level.  Everything that can fail happens before any of
+    /// the world's resources get replaced, so if it does, whatever
+    /// simulation was already running in `world` carries on unharmed.
+    pub fn new(world: &mut World, level: &LevelDef) -> Result<Self, Err> {
+        level.validate()?;
+        let mut rng = GameRng::new(world.seed);
+        let mut entities = Vec::new();
+        let player_entity = match Self::create_entities(world, level, &mut rng, &mut entities) {
+            Ok(player) => player,
+            Err(e) => {
+                for entity in entities {
+                    world.despawn(entity);
+                }
+                world.maintain();
+                return Err(e);
+            }
+        };
+        // LevelDef::validate() makes sure there's at least one planet,
+        // and they get created first.
+        let planet_entity = entities[0];
+
+        world
+            .specs_world
+            .add_resource(EventChannel::<CollisionEvent>::new());
+        let mut game_event_channel = EventChannel::<GameEvent>::new();
+        let game_events = game_event_channel.register_reader();
+        world.specs_world.add_resource(game_event_channel);
+        let mut dispatcher = Self::register_systems();
+        dispatcher.setup(&mut world.specs_world.res);
+        *world.specs_world.write_resource::<RunStats>() = RunStats::default();
+        *world.specs_world.write_resource::<Integrator>() = level.integrator;
+        *world.specs_world.write_resource::<GameRng>() = rng;
+
+        Ok(Simulation {
+            dispatcher,
+            player_entity,
+            planet_entity,
+            planet_radius: level.planets[0].radius,
+            entities,
+            tick: 0,
+            game_events,
+            fail: level.fail.clone(),
+        })
+    }
+
+    /// Creates everything in the level, planets first, and returns the
+    /// player.  Everything goes in `entities` as soon as it's made, so
+    /// if something fails partway the caller can clean up what's there.
+    fn create_entities(
+        world: &mut World,
+        level: &LevelDef,
+        rng: &mut GameRng,
+        entities: &mut Vec<specs::Entity>,
+    ) -> Result<specs::Entity, Err> {
+        for planet in &level.planets {
+            let parent = planet.orbits.map(|i| &level.planets[i]);
+            entities.push(Self::create_planet(world, planet, parent)?);
+        }
+        let home_planet = &level.planets[0];
+        let player_entity = Self::create_player(world, &level.player, level.controls, home_planet)?;
+        entities.push(player_entity);
+        for obstacle in &level.obstacles {
+            let planet = &level.planets[obstacle.planet];
+            entities.push(Self::create_obstacle(world, obstacle, planet)?);
+        }
+        if let Some(ref procedural) = level.procedural_obstacles {
+            let planet = &level.planets[procedural.planet];
+            let angles = procgen::obstacle_angles(&mut rng.rng, procedural, planet, &level.player);
+            for angle in angles {
+                let obstacle = ObstacleDef {
+                    planet: procedural.planet,
+                    angle,
+                    halfwidth: procedural.halfwidth,
+                };
+                entities.push(Self::create_obstacle(world, &obstacle, planet)?);
+            }
+        }
+        Ok(player_entity)
+    }
+
+    /// Removes everything this simulation created from the world,
+    /// both from specs and ncollide.
+    pub fn destroy(&mut self, world: &mut World) {
+        let entities: Vec<_> = self.entities.drain(..).collect();
+        for &entity in &entities {
+            world.despawn(entity);
+        }
+        world.maintain();
+        let leftover = world
+            .specs_world
+            .read_resource::<CollisionWorld>()
+            .collision_objects()
+            .filter(|obj| entities.contains(obj.data()))
+            .count();
+        if leftover > 0 {
+            warn!("{} collision objects left over after destroying a level", leftover);
+        }
+    }
+
+    /// Replaces this with a fresh simulation of `level`.  The new one
+    /// gets built before this one is destroyed, so if `level` won't
+    /// build we're left with the old one, still running.
+    pub fn restart(&mut self, world: &mut World, level: &LevelDef) -> Result<(), Err> {
+        let new = Simulation::new(world, level)?;
+        self.destroy(world);
+        *self = new;
+        Ok(())
+    }
+
+    /// Creates a ghost to go along with the level.  It doesn't collide
+    /// with anything or get simulated, but it gets cleaned up with
+    /// everything else.
+    pub fn create_ghost(&mut self, world: &mut World, alpha: f32) -> specs::Entity {
+        let (position, rotation) = self.player_position(world);
+        let (mut sprite, animation) = astronaut_sprite();
+        sprite.alpha = alpha;
+        let entity = world
+            .specs_world
+            .create_entity()
+            .with(Ghost { position, rotation })
+            .with(sprite)
+            .with(animation)
+            .build();
+        self.entities.push(entity);
+        entity
+    }
+
+    fn register_systems() -> specs::Dispatcher<'static, 'static> {
+        let gravity = GravitySystem {};
+        specs::DispatcherBuilder::new()
+            .with(gravity, "sys_gravity", &[])
+            .with(PlayerTumbleSystem {}, "sys_tumble", &[])
+            .with(AnimationSystem {}, "sys_animation", &["sys_tumble"])
+            .with(NCollideMotionSystem {}, "sys_motion", &["sys_gravity"])
+            .with(
+                CollisionEventSystem {},
+                "sys_collision_events",
+                &["sys_motion", "sys_tumble"],
+            )
+            .with(
+                GroundingSystem::default(),
+                "sys_grounding",
+                &["sys_collision_events"],
+            )
+            .with(
+                ObstacleHitSystem::default(),
+                "sys_obstacle_hits",
+                &["sys_collision_events"],
+            )
+            // .with(DebugPrinterSystem {}, "sys_debugprint", &[])
+            .build()
+    }
+
+    /// Runs one tick of the game.
+    pub fn step(&mut self, world: &mut World) {
+        self.run_player_motion(world);
+        self.dispatcher.dispatch(&mut world.specs_world.res);
+        // Anything the systems deleted goes away now, collision
+        // object and all, rather than hanging around half-dead.
+        world.maintain();
+        self.update_stats(world);
+        self.tick += 1;
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Returns everything interesting that's happened since the
+    /// last time this was called.
+    pub fn game_events(&mut self, world: &World) -> Vec<GameEvent> {
+        let channel = world.specs_world.read_resource::<EventChannel<GameEvent>>();
+        channel.read(&mut self.game_events).cloned().collect()
+    }
+
+    /// Feeds an input event to the player.
+    ///
+    /// This has to only depend on the event itself, not on any other
+    /// input state, or replays won't come out the same.
+    pub fn input(&self, world: &mut World, ev: input::InputEvent, started: bool) {
+        if let InputEffect::Button(input::Button::Jump) = ev {
+            self.set_jumping(world, started);
+            return;
+        }
+        let mut players = world.specs_world.write_storage::<Player>();
+        let player = match players.get_mut(self.player_entity) {
+            Some(player) => player,
+            None => return,
+        };
+        match ev {
+            InputEffect::Axis(input::Axis::Horz, true) => player.forward = started,
+            InputEffect::Axis(input::Axis::Horz, false) => player.back = started,
+            InputEffect::Axis(input::Axis::Vert, false) => player.fast_falling = started,
+            _ => (),
+        }
+    }
+
+    /// Returns the stats for the run so far.
+    pub fn stats(&self, world: &World) -> RunStats {
+        world.specs_world.read_resource::<RunStats>().clone()
+    }
+
+    fn update_stats(&self, world: &mut World) {
+        let dt = world.specs_world.read_resource::<DeltaTime>().0;
+        let motions = world.specs_world.read_storage::<Motion>();
+        let mut stats = world.specs_world.write_resource::<RunStats>();
+        stats.time += dt;
+        if let Some(motion) = motions.get(self.player_entity) {
+            stats.peak_velocity = stats.peak_velocity.max(na::norm(&motion.velocity));
+        }
+    }
+
+    /// Returns why the player has lost, if they have.
+    pub fn failed(&self, world: &World) -> Option<FailReason> {
+        let stats = world.specs_world.read_resource::<RunStats>();
+        if let Some(limit) = self.fail.time_limit {
+            if stats.time >= limit {
+                return Some(FailReason::OutOfTime);
+            }
+        }
+        if let Some(max) = self.fail.max_obstacle_hits {
+            if stats.obstacles_hit > max {
+                return Some(FailReason::TooManyHits);
+            }
+        }
+        None
+    }
+
+    /// Returns true if the player has made it into a stable orbit
+    /// around the planet, or escaped it entirely.
+    ///
+    /// Our gravity falls off with `1/d` rather than `1/d^2`, so there
+    /// isn't really an escape velocity; instead we check whether the
+    /// lowest point of the player's current trajectory clears the
+    /// ground, or whether they're just plain far away.
+    pub fn reached_orbit(&self, world: &World) -> bool {
+        let players = world.specs_world.read_storage::<Player>();
+        let motions = world.specs_world.read_storage::<Motion>();
+        let gravities = world.specs_world.read_storage::<Gravity>();
+        let on_ground = players
+            .get(self.player_entity)
+            .map(|p| p.on_ground)
+            .unwrap_or(true);
+        let (motion, gravity) = match (
+            motions.get(self.player_entity),
+            gravities.get(self.planet_entity),
+        ) {
+            (Some(m), Some(g)) => (m, g),
+            _ => return false,
+        };
+        if on_ground {
+            return false;
+        }
+
+        let (player_position, _) = self.player_position(world);
+        let colliders = world.specs_world.read_storage::<Collider>();
+        let ncollide_world = world.specs_world.read_resource::<CollisionWorld>();
+        let planet_position = match colliders
+            .get(self.planet_entity)
+            .and_then(|c| collision_object_position(&*ncollide_world, c))
+        {
+            Some((position, _)) => position,
+            None => return false,
+        };
+
+        let offset = player_position - planet_position;
+        let distance = na::norm(&offset);
+        if distance > self.planet_radius * ESCAPE_RADII {
+            return true;
+        }
+        let lowest_allowed = self.planet_radius + ORBIT_CLEARANCE;
+        if distance <= lowest_allowed {
+            return false;
+        }
+
+        // With a potential of `force * ln(d)`, the player can only reach
+        // distances where their radial kinetic energy would be positive,
+        // which is an interval around where they are now.  So if there's
+        // no radial energy left at `lowest_allowed`, they never get down
+        // that far.
+        let normal = offset / distance;
+        let radial_speed = na::dot(&motion.velocity, &normal);
+        let tangential_speed = na::norm(&(motion.velocity - normal * radial_speed));
+        let angular_momentum = distance * tangential_speed;
+        let energy = na::norm_squared(&motion.velocity) / 2.0 + gravity.force * distance.ln();
+        let radial_energy_at = |d: f32| {
+            energy
+                - gravity.force * d.ln()
+                - (angular_momentum * angular_momentum) / (2.0 * d * d)
+        };
+        radial_energy_at(lowest_allowed) < 0.0
+    }
+
+    /// Sets whether or not the player is holding the jump button.
+    pub fn set_jumping(&self, world: &mut World, jumping: bool) {
+        if let Some(player) = world
+            .specs_world
+            .write_storage::<Player>()
+            .get_mut(self.player_entity)
+        {
+            player.jumping = jumping;
+        }
+    }
+
+    /// Returns the player's position and rotation.
+    pub fn player_position(&self, world: &World) -> (Point2, f32) {
+        let colliders = world.specs_world.read_storage::<Collider>();
+        let ncollide_world = world.specs_world.read_resource::<CollisionWorld>();
+        colliders
+            .get(self.player_entity)
+            .and_then(|c| collision_object_position(&*ncollide_world, c))
+            .expect("Player w/o collision object?")
+    }
+
+    /// Creates the player standing on top of the given planet.
+    fn create_player(
+        world: &mut World,
+        player_def: &PlayerDef,
+        control_mode: ControlMode,
+        planet: &PlanetDef,
+    ) -> Result<specs::Entity, Err> {
+        let player_halfwidth = player_def.halfwidth;
+        let player_halfheight = player_def.halfheight;
+        let player_offset = planet.radius + player_halfheight * 3.0;
+        let (sprite, animation) = astronaut_sprite();
+        // Make the player entity
+        let entity = world
+            .specs_world
+            .create_entity()
+            .with(Player {
+                on_ground: false,
+                jumping: false,
+                jump_force: player_def.jump_force,
+                velocity: 0.0,
+                run_acceleration: player_def.run_acceleration,
+                run_force: player_def.run_force,
+                tumbling_timer: 0.0,
+                friction: 0.0,
+                control_mode,
+                forward: false,
+                back: false,
+                fast_falling: false,
+            })
+            .with(Motion::new(Vector2::new(player_def.start_speed, 0.0)))
+            .with(Mass::default())
+            .with(sprite)
+            .with(animation)
+            .build();
+
+        // Player collision info
+        let shape = nc::shape::Cuboid::new(Vector2::new(player_halfwidth, player_halfheight));
+        let player_collide_group = Layer::Player.collision_groups();
+        let query_type = nc::world::GeometricQueryType::Contacts(0.0, 0.0);
+
+        let player_collider = {
+            let mut collide_world = world.specs_world.write_resource::<CollisionWorld>();
+            let player_handle = collide_world.add(
+                na::Isometry2::new(
+                    na::Vector2::new(planet.position.0, planet.position.1 - player_offset),
+                    na::zero(),
+                ),
+                nc::shape::ShapeHandle::new(shape.clone()),
+                player_collide_group,
+                query_type,
+                entity,
+            );
+
+            Collider {
+                object_handle: player_handle,
+            }
+        };
+        // Insert the collider.
+        world
+            .specs_world
+            .write_storage::<Collider>()
+            .insert(entity, player_collider)?;
+        Ok(entity)
+    }
+
+    /// Creates a planet, or a moon if it has a `parent` to orbit.
+    fn create_planet(
+        world: &mut World,
+        planet: &PlanetDef,
+        parent: Option<&PlanetDef>,
+    ) -> Result<specs::Entity, Err> {
+        let planet_radius = planet.radius;
+        let gravity = planet.gravity;
+        // Make the world entity
+        let mut builder = world
+            .specs_world
+            .create_entity()
+            .with(Mesh::new(MeshShape::Circle {
+                radius: planet_radius,
+            }))
+            .with(Gravity { force: gravity });
+        if let Some(parent) = parent {
+            // With gravity falling off as 1/d, a circular orbit needs
+            // v^2/d = force/d, so the speed is the same at any distance.
+            // Go the same way round as the player runs.
+            let offset = Vector2::new(
+                planet.position.0 - parent.position.0,
+                planet.position.1 - parent.position.1,
+            );
+            let direction = na::Rotation2::new(f32::consts::PI / 2.0) * na::normalize(&offset);
+            builder = builder
+                .with(Motion::new(direction * parent.gravity.sqrt()))
+                .with(Mass::default());
+        }
+        let entity = builder.build();
+
+        // Planet collision info
+        let ball = nc::shape::Ball::new(planet_radius);
+        let terrain_collide_group = Layer::Planet.collision_groups();
+        let query_type = nc::world::GeometricQueryType::Contacts(0.0, 0.0);
+
+        let planet_collider = {
+            let mut collide_world = world.specs_world.write_resource::<CollisionWorld>();
+            let planet_handle = collide_world.add(
+                na::Isometry2::new(
+                    Vector2::new(planet.position.0, planet.position.1),
+                    na::zero(),
+                ),
+                nc::shape::ShapeHandle::new(ball.clone()),
+                terrain_collide_group,
+                query_type,
+                entity,
+            );
+
+            Collider {
+                object_handle: planet_handle,
+            }
+        };
+        // Insert the collider.
+        world
+            .specs_world
+            .write_storage::<Collider>()
+            .insert(entity, planet_collider)?;
+        Ok(entity)
+    }
+
+    /// Creates an obstacle sitting on the given planet.
+    fn create_obstacle(
+        world: &mut World,
+        obstacle: &ObstacleDef,
+        planet: &PlanetDef,
+    ) -> Result<specs::Entity, Err> {
+        let angle = obstacle.angle.to_radians();
+        let obstacle_halfwidth = obstacle.halfwidth;
+        let obstacle_offset = planet.radius + obstacle_halfwidth;
+        // Make the player entity
+        let entity = world
+            .specs_world
+            .create_entity()
+            .with(Obstacle {})
+            .with(Mesh::new(MeshShape::Square {
+                halfwidth: obstacle_halfwidth,
+            }))
+            .build();
+
+        // collision info
+        let shape = nc::shape::Cuboid::new(Vector2::new(obstacle_halfwidth, obstacle_halfwidth));
+        let obstacle_collide_group = Layer::Obstacle.collision_groups();
+        let query_type = nc::world::GeometricQueryType::Contacts(0.0, 0.0);
+
+        let obstacle_collider = {
+            let mut collide_world = world.specs_world.write_resource::<CollisionWorld>();
+            // sigh
+            let x = planet.position.0 + f32::cos(angle) * obstacle_offset;
+            let y = planet.position.1 + f32::sin(angle) * obstacle_offset;
+            let handle = collide_world.add(
+                na::Isometry2::new(na::Vector2::new(x, y), angle),
+                nc::shape::ShapeHandle::new(shape.clone()),
+                obstacle_collide_group,
+                query_type,
+                entity,
+            );
+
+            Collider {
+                object_handle: handle,
+            }
+        };
+        // Insert the collider.
+        world
+            .specs_world
+            .write_storage::<Collider>()
+            .insert(entity, obstacle_collider)?;
+        Ok(entity)
+    }
+
+    /// This is really hard to express as a specs System so we roll our own.
+    fn run_player_motion(&mut self, world: &mut World) {
+        let dt = world.specs_world.read_resource::<DeltaTime>().0;
+        if let Some(player) = world
+            .specs_world
+            .write_storage::<Player>()
+            .get_mut(self.player_entity)
+        {
+            let colliders = world.specs_world.read_storage::<Collider>();
+            let mut motions = world.specs_world.write_storage::<Motion>();
+            let masses = world.specs_world.read_storage::<Mass>();
+            let mut ncollide_world = world.specs_world.write_resource::<CollisionWorld>();
+            let mut game_events = world.specs_world.write_resource::<EventChannel<GameEvent>>();
+
+            // "Down" is towards whatever's pulling on us hardest, or the
+            // planet we started on if nothing has yet.
+            let ground_entity = masses
+                .get(self.player_entity)
+                .and_then(|m| m.dominant_source)
+                .filter(|e| colliders.get(*e).is_some())
+                .unwrap_or(self.planet_entity);
+            let ground_velocity = motions
+                .get(ground_entity)
+                .map(|m| m.velocity)
+                .unwrap_or_else(na::zero);
+
+            let player_motion = motions
+                .get_mut(self.player_entity)
+                .expect("Player w/o motion?");
+            let player_collider = colliders
+                .get(self.player_entity)
+                .expect("Player w/o collider?");
+            let ground_collider = colliders
+                .get(ground_entity)
+                .expect("Planet w/o collider?");
+            let (player_position, planet_position) = match (
+                collision_object_position(&*ncollide_world, player_collider),
+                collision_object_position(&*ncollide_world, ground_collider),
+            ) {
+                (Some((player, _)), Some((planet, _))) => (player, planet),
+                // `ColliderCleanupSystem` will sort it out.
+                _ => return,
+            };
+
+            let offset = player_position - planet_position;
+            let normal = offset / na::norm(&offset);
+            if player.on_ground {
+                // We only want to zero the Y component
+                // of the velocity... that is, the portion
+                // towards the planet.
+                // So we take the projection of velocity onto
+                // the toward-the-planet offset vector.
+                // The ground might be a moon, so it's relative to that.
+                let projection = na::dot(&(player_motion.velocity - ground_velocity), &normal);
+                // debug!("Projection is {:?}, offset is {:?}, velocity is {}", projection, offset, player_motion.velocity);
+
+                player_motion.velocity -= na::normalize(&offset) * projection;
+
+                player_motion.acceleration = na::zero();
+                // Jump; it's an impulse, so it goes straight into velocity.
+                if player.jumping {
+                    player_motion.velocity += normal * player.jump_force;
+                    player.on_ground = false;
+                    game_events.single_write(GameEvent::Jumped);
+                }
+
+                // Walk
+                let rot = na::Rotation2::new(f32::consts::PI / 2.0);
+                let run_speed = rot * (normal * player.velocity);
+                player_motion.acceleration += run_speed * player.run_force;
+            } else if player.fast_falling && player.control_mode == ControlMode::Steered {
+                player_motion.acceleration -= normal * FAST_FALL_ACCELERATION;
+            }
+            // The friction term is probably wrong since it will probably slow falling
+            // as well, but fuck it, it doesn't seem to make the player go backwards.
+            player.velocity +=
+                (player.run_acceleration * player.run_input() - (player.velocity * player.friction)) * dt;
+
+            player_motion.velocity +=
+                (player_motion.acceleration - (player_motion.velocity * player.friction)) * dt;
+            player_motion.acceleration = na::zero();
+
+            // Rotate to stand upright on planet.
+            let player_angle = f32::atan2(offset.x, -offset.y);
+
+            let new_position = {
+                // We already know it's there from getting its position.
+                let collision_obj = ncollide_world
+                    .collision_object(player_collider.object_handle)
+                    .expect("Player collision object vanished?");
+                let mut new_position = collision_obj.position().clone();
+                new_position.append_translation_mut(&na::Translation::from_vector(
+                    player_motion.velocity * dt,
+                ));
+                new_position.rotation = na::UnitComplex::from_angle(player_angle);
+                new_position
+            };
+            ncollide_world.set_position(player_collider.object_handle, new_position);
+        }
+    }
+}
+
+/// Takes a collision object handle and returns the location and orientation
+/// of the object, or `None` if it's been removed from ncollide but not
+/// specs yet.
+pub fn collision_object_position(
+    ncollide_world: &CollisionWorld,
+    collider: &Collider,
+) -> Option<(Point2, f32)> {
+    let collision_object = ncollide_world.collision_object(collider.object_handle)?;
+    let isometry = collision_object.position();
+    let annoying_new_pos =
+        Point2::new(isometry.translation.vector.x, isometry.translation.vector.y);
+    let annoying_new_angle = isometry.rotation.angle();
+    Some((annoying_new_pos, annoying_new_angle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path;
+
+    use replay::{Replay, ReplayPlayer};
+    use specs::Join;
+
+    fn resource_dir() -> path::PathBuf {
+        path::Path::new(env!("CARGO_MANIFEST_DIR")).join("resources")
+    }
+
+    fn load_level(file_name: &str) -> LevelDef {
+        let path = resource_dir().join("levels").join(file_name);
+        let file = fs::File::open(&path).expect("Could not open level file");
+        LevelDef::from_reader(file).expect("Could not parse level file")
+    }
+
+    fn world_with_seed(seed: u64) -> World {
+        let mut world = World::with_resource_dir(resource_dir());
+        world.seed = seed;
+        world
+    }
+
+    fn collision_object_count(world: &World) -> usize {
+        world
+            .specs_world
+            .read_resource::<CollisionWorld>()
+            .collision_objects()
+            .count()
+    }
+
+    fn live_entity_count(world: &World) -> usize {
+        (&*world.specs_world.entities()).join().count()
+    }
+
+    fn on_ground(sim: &Simulation, world: &World) -> bool {
+        world
+            .specs_world
+            .read_storage::<Player>()
+            .get(sim.player_entity)
+            .expect("No player?")
+            .on_ground
+    }
+
+    #[test]
+    fn player_lands_and_runs() {
+        let level = load_level("level1.ron");
+        let mut world = world_with_seed(1);
+        let mut sim = Simulation::new(&mut world, &level).unwrap();
+        let planet = &level.planets[0];
+        let center = Point2::new(planet.position.0, planet.position.1);
+        let angle_of = |p: Point2| f32::atan2(p.y - center.y, p.x - center.x);
+
+        let (start, _) = sim.player_position(&world);
+        let mut landed = false;
+        for _ in 0..300 {
+            sim.step(&mut world);
+            landed |= on_ground(&sim, &world);
+        }
+        assert!(landed, "Player never touched the ground");
+
+        let (end, _) = sim.player_position(&world);
+        assert!(
+            angle_of(end) > angle_of(start) + 0.01,
+            "Player didn't run forward: went from {} to {}",
+            start,
+            end
+        );
+        let height = na::distance(&end, &center) - planet.radius;
+        assert!(height < 100.0, "Player is {} above the ground", height);
+    }
+
+    #[test]
+    fn replays_come_out_the_same() {
+        // Level 2 has procedural obstacles, so the seed matters too.
+        let level = load_level("level2.ron");
+        let seed = 12345;
+        let ticks = 600;
+        let jump = InputEffect::Button(input::Button::Jump);
+
+        let mut world = world_with_seed(seed);
+        let mut sim = Simulation::new(&mut world, &level).unwrap();
+        let mut recording = Replay::new(&level.name, seed);
+        for tick in 0..ticks {
+            // Hold jump for a bit every second and a half or so.
+            if tick % 90 == 30 || tick % 90 == 40 {
+                let started = tick % 90 == 30;
+                recording.record(sim.tick(), jump, started);
+                sim.input(&mut world, jump, started);
+            }
+            sim.step(&mut world);
+        }
+        let (position, rotation) = sim.player_position(&world);
+
+        let mut world = world_with_seed(seed);
+        let mut sim = Simulation::new(&mut world, &level).unwrap();
+        let mut playback = ReplayPlayer::new(recording);
+        for _ in 0..ticks {
+            for recorded in playback.inputs_for_tick(sim.tick()) {
+                sim.input(&mut world, recorded.effect.into(), recorded.started);
+            }
+            sim.step(&mut world);
+        }
+        let (replayed_position, replayed_rotation) = sim.player_position(&world);
+
+        assert_eq!(position.x.to_bits(), replayed_position.x.to_bits());
+        assert_eq!(position.y.to_bits(), replayed_position.y.to_bits());
+        assert_eq!(rotation.to_bits(), replayed_rotation.to_bits());
+    }
+
+    #[test]
+    fn deleted_entities_lose_their_collision_objects() {
+        let level = load_level("level1.ron");
+        let mut world = world_with_seed(1);
+        let mut sim = Simulation::new(&mut world, &level).unwrap();
+        let before = collision_object_count(&world);
+        // Deleting straight from specs, not through `World::despawn()`.
+        let obstacle = {
+            let entities = world.specs_world.entities();
+            let obstacles = world.specs_world.read_storage::<Obstacle>();
+            let (obstacle, _) = (&*entities, &obstacles).join().next().expect("No obstacles?");
+            entities.delete(obstacle).unwrap();
+            obstacle
+        };
+        sim.step(&mut world);
+        assert!(!world.specs_world.entities().is_alive(obstacle));
+        assert_eq!(collision_object_count(&world), before - 1);
+        // And drawing-type lookups don't fall over on the next tick.
+        sim.step(&mut world);
+        sim.player_position(&world);
+        sim.reached_orbit(&world);
+    }
+
+    #[test]
+    fn restarting_does_not_leak() {
+        // Level 2 has procedural obstacles, level 4 has a moon.
+        for file_name in &["level2.ron", "level4.ron"] {
+            let level = load_level(file_name);
+            let mut world = world_with_seed(7);
+            let mut sim = Simulation::new(&mut world, &level).unwrap();
+            sim.create_ghost(&mut world, 0.5);
+            let objects = collision_object_count(&world);
+            let entities = live_entity_count(&world);
+            assert!(objects > 0 && entities > 0);
+            for _ in 0..5 {
+                for _ in 0..30 {
+                    sim.step(&mut world);
+                }
+                sim.destroy(&mut world);
+                assert_eq!(collision_object_count(&world), 0);
+                assert_eq!(live_entity_count(&world), 0);
+
+                sim = Simulation::new(&mut world, &level).unwrap();
+                sim.create_ghost(&mut world, 0.5);
+                assert_eq!(collision_object_count(&world), objects);
+                assert_eq!(live_entity_count(&world), entities);
+            }
+        }
+    }
+
+    #[test]
+    fn restarting_into_a_broken_level_keeps_the_old_one() {
+        let level = load_level("level4.ron");
+        let mut world = world_with_seed(3);
+        let mut sim = Simulation::new(&mut world, &level).unwrap();
+        let objects = collision_object_count(&world);
+        let entities = live_entity_count(&world);
+        for _ in 0..60 {
+            sim.step(&mut world);
+        }
+
+        let mut broken = level.clone();
+        broken.obstacles.push(ObstacleDef {
+            planet: 99,
+            angle: 0.0,
+            halfwidth: 10.0,
+        });
+        assert!(sim.restart(&mut world, &broken).is_err());
+        assert_eq!(sim.tick(), 60);
+        assert_eq!(collision_object_count(&world), objects);
+        assert_eq!(live_entity_count(&world), entities);
+
+        // Still going, without having been reset.
+        for _ in 0..60 {
+            sim.step(&mut world);
+        }
+        sim.player_position(&world);
+        sim.reached_orbit(&world);
+        sim.game_events(&world);
+        assert!((sim.stats(&world).time - 2.0).abs() < 0.01);
+
+        sim.restart(&mut world, &level).unwrap();
+        assert_eq!(sim.tick(), 0);
+        assert_eq!(collision_object_count(&world), objects);
+        assert_eq!(live_entity_count(&world), entities);
+    }
+}
@@ -1,57 +1,81 @@
 //! specs systems.
 use nalgebra as na;
+use ncollide2d as nc;
+use shrev::{EventChannel, ReaderId};
 use specs::{self, Join};
+use std::collections::{HashMap, HashSet};
 use util::*;
 
 use components::*;
+use events::{CollisionEvent, Contact, GameEvent};
+use layers::Layer;
+use world::{DeltaTime, Integrator, RunStats};
 
+/// Pulls everything with a `Mass` towards everything with a `Gravity`,
+/// and keeps track of which source pulls hardest on each.
 pub struct GravitySystem {}
 
 impl<'a> specs::System<'a> for GravitySystem {
     type SystemData = (
+        specs::Entities<'a>,
         specs::WriteStorage<'a, Motion>,
         specs::ReadStorage<'a, Gravity>,
         specs::ReadStorage<'a, Collider>,
-        specs::ReadStorage<'a, Mass>,
+        specs::WriteStorage<'a, Mass>,
         specs::Read<'a, CollisionWorld, specs::shred::PanicHandler>,
     );
 
-    fn run(&mut self, (mut motion, gravity, collider, mass, ncollide_world): Self::SystemData) {
-        // I know we'll only ever have one gravity source in the game,
-        // shut up.
-        let mut gravity_sources: Vec<(Point2, f32)> = Vec::new();
-        for (collider, gravity) in (&collider, &gravity).join() {
+    fn run(
+        &mut self,
+        (entities, mut motion, gravity, collider, mut mass, ncollide_world): Self::SystemData,
+    ) {
+        let mut gravity_sources: Vec<(specs::Entity, Point2, f32)> = Vec::new();
+        for (entity, collider, gravity) in (&*entities, &collider, &gravity).join() {
             let grav_position = {
-                let collision_obj = ncollide_world
-                    .collision_object(collider.object_handle)
-                    .expect(
-                        "Invalid collision object; was it removed from ncollide but not specs?",
-                    );
+                // `ColliderCleanupSystem` will get rid of the entity soon.
+                let collision_obj = match ncollide_world.collision_object(collider.object_handle)
+                {
+                    Some(obj) => obj,
+                    None => continue,
+                };
                 Point2 {
                     coords: collision_obj.position().translation.vector,
                 }
             };
-            gravity_sources.push((grav_position, gravity.force));
+            gravity_sources.push((entity, grav_position, gravity.force));
         }
 
-        for (motion, collider, _mass) in (&mut motion, &collider, &mass).join() {
+        for (entity, motion, collider, mass) in
+            (&*entities, &mut motion, &collider, &mut mass).join()
+        {
             let other_position = {
-                let collision_obj = ncollide_world
-                    .collision_object(collider.object_handle)
-                    .expect(
-                        "Invalid collision object; was it removed from ncollide but not specs?",
-                    );
+                let collision_obj = match ncollide_world.collision_object(collider.object_handle)
+                {
+                    Some(obj) => obj,
+                    None => continue,
+                };
                 Point2 {
                     coords: collision_obj.position().translation.vector,
                 }
             };
 
-            for (grav_position, grav_force) in &gravity_sources {
+            let mut strongest = 0.0;
+            mass.dominant_source = None;
+            for &(source, grav_position, grav_force) in &gravity_sources {
+                // Moons have both mass and gravity; don't pull on yourself.
+                if source == entity {
+                    continue;
+                }
                 let offset = grav_position - other_position;
                 let distance = na::norm(&offset);
                 // avoid punishingly small distances
                 if !distance.is_nan() && distance > 0.1 {
-                    motion.acceleration += offset * (grav_force / (distance * distance));
+                    let strength = grav_force / distance;
+                    motion.acceleration += offset * (strength / distance);
+                    if strength > strongest {
+                        strongest = strength;
+                        mass.dominant_source = Some(source);
+                    }
                 } else {
                     debug!(
                         "Something horrible happened in GravitySystem: distance {}",
@@ -68,45 +92,264 @@ impl<'a> specs::System<'a> for GravitySystem {
 pub struct PlayerTumbleSystem {}
 
 impl<'a> specs::System<'a> for PlayerTumbleSystem {
-    type SystemData = (specs::WriteStorage<'a, Player>,);
+    type SystemData = (
+        specs::Read<'a, DeltaTime>,
+        specs::WriteStorage<'a, Player>,
+        specs::WriteStorage<'a, Sprite>,
+    );
 
-    fn run(&mut self, (mut player,): Self::SystemData) {
-        for (player,) in (&mut player,).join() {
+    fn run(&mut self, (dt, mut player, mut sprite): Self::SystemData) {
+        for (player, sprite) in (&mut player, (&mut sprite).maybe()).join() {
             if player.tumbling_timer > 0.0 {
-                // BUGGO: TODO: Aieeee, time
-                player.tumbling_timer -= 0.1;
-                player.friction = 0.1;
+                player.tumbling_timer -= dt.0;
+                player.friction = TUMBLE_FRICTION;
             } else {
                 player.friction = 0.0;
             }
+            if let Some(sprite) = sprite {
+                sprite.rotation = -player.tumbling_timer.max(0.0) * TUMBLE_SPIN;
+            }
+        }
+    }
+}
+
+/// Advances every `Animation` and points its `Sprite` at the current frame.
+pub struct AnimationSystem {}
+
+impl<'a> specs::System<'a> for AnimationSystem {
+    type SystemData = (
+        specs::Read<'a, DeltaTime>,
+        specs::WriteStorage<'a, Animation>,
+        specs::WriteStorage<'a, Sprite>,
+    );
+
+    fn run(&mut self, (dt, mut animation, mut sprite): Self::SystemData) {
+        for (animation, sprite) in (&mut animation, &mut sprite).join() {
+            animation.timer += dt.0;
+            loop {
+                let duration = match animation.current_frame() {
+                    Some(frame) => frame.duration,
+                    None => break,
+                };
+                // A zero duration would never get out of this loop.
+                if duration <= 0.0 || animation.timer < duration {
+                    break;
+                }
+                animation.timer -= duration;
+                animation.advance();
+            }
+            if let Some(frame) = animation.current_frame() {
+                if sprite.image != frame.image {
+                    sprite.image = frame.image.clone();
+                }
+                sprite.frame = frame.index;
+            }
+        }
+    }
+}
+
+/// Updates the ncollide world and turns its contact events into
+/// `CollisionEvent`s, so anything that cares about things touching
+/// can just read those.
+pub struct CollisionEventSystem {}
+
+impl CollisionEventSystem {
+    fn contact(cobj1: &CollisionObject, cobj2: &CollisionObject) -> Contact {
+        Contact {
+            a: *cobj1.data(),
+            b: *cobj2.data(),
+            a_groups: *cobj1.collision_groups(),
+            b_groups: *cobj2.collision_groups(),
+        }
+    }
+}
+
+impl<'a> specs::System<'a> for CollisionEventSystem {
+    type SystemData = (
+        specs::Write<'a, CollisionWorld, specs::shred::PanicHandler>,
+        specs::Write<'a, EventChannel<CollisionEvent>>,
+    );
+
+    fn run(&mut self, (mut ncollide_world, mut events): Self::SystemData) {
+        ncollide_world.update();
+        let mut new_events = Vec::new();
+        for e in ncollide_world.contact_events() {
+            let (handle1, handle2) = match *e {
+                nc::events::ContactEvent::Started(h1, h2) => (h1, h2),
+                nc::events::ContactEvent::Stopped(h1, h2) => (h1, h2),
+            };
+            // It's apparently possible for the collision pair to have
+            // no contacts...
+            // Possibly if one object is entirely inside another?
+            if ncollide_world.contact_pair(handle1, handle2).is_none() {
+                continue;
+            }
+            let (cobj1, cobj2) = match (
+                ncollide_world.collision_object(handle1),
+                ncollide_world.collision_object(handle2),
+            ) {
+                (Some(c1), Some(c2)) => (c1, c2),
+                _ => continue,
+            };
+            let contact = Self::contact(cobj1, cobj2);
+            new_events.push(match *e {
+                nc::events::ContactEvent::Started(..) => CollisionEvent::Started(contact),
+                nc::events::ContactEvent::Stopped(..) => CollisionEvent::Stopped(contact),
+            });
+        }
+        events.iter_write(new_events);
+    }
+}
+
+/// Keeps track of whether the player is standing on a planet.
+/// They can be touching more than one at once where planets are
+/// close together, so leaving one doesn't mean they're in the air.
+#[derive(Default)]
+pub struct GroundingSystem {
+    reader: Option<ReaderId<CollisionEvent>>,
+    /// The planets each player is touching.
+    contacts: HashMap<specs::Entity, HashSet<specs::Entity>>,
+}
+
+impl<'a> specs::System<'a> for GroundingSystem {
+    type SystemData = (
+        specs::Read<'a, EventChannel<CollisionEvent>>,
+        specs::Write<'a, EventChannel<GameEvent>>,
+        specs::WriteStorage<'a, Player>,
+    );
+
+    fn setup(&mut self, res: &mut specs::Resources) {
+        use specs::prelude::SystemData;
+        Self::SystemData::setup(res);
+        self.reader = Some(
+            res.fetch_mut::<EventChannel<CollisionEvent>>()
+                .register_reader(),
+        );
+    }
+
+    fn run(&mut self, (events, mut game_events, mut player): Self::SystemData) {
+        let reader = self.reader.as_mut().expect("GroundingSystem wasn't set up");
+        for event in events.read(reader) {
+            let (contact, touching) = match *event {
+                CollisionEvent::Started(ref contact) => (contact, true),
+                CollisionEvent::Stopped(ref contact) => (contact, false),
+            };
+            for contact in &contact.both_ways() {
+                if let Some(player) = player.get_mut(contact.a) {
+                    if Layer::Planet.is_in(&contact.b_groups) {
+                        let planets = self.contacts.entry(contact.a).or_insert_with(HashSet::new);
+                        if touching {
+                            planets.insert(contact.b);
+                        } else {
+                            planets.remove(&contact.b);
+                        }
+                        let on_ground = !planets.is_empty();
+                        if on_ground && !player.on_ground {
+                            game_events.single_write(GameEvent::Landed);
+                        }
+                        player.on_ground = on_ground;
+                    }
+                }
+            }
         }
     }
 }
 
+/// Makes the player tumble when they run into an obstacle.
+#[derive(Default)]
+pub struct ObstacleHitSystem {
+    reader: Option<ReaderId<CollisionEvent>>,
+}
+
+impl<'a> specs::System<'a> for ObstacleHitSystem {
+    type SystemData = (
+        specs::Read<'a, EventChannel<CollisionEvent>>,
+        specs::Write<'a, EventChannel<GameEvent>>,
+        specs::WriteStorage<'a, Player>,
+        specs::Write<'a, RunStats>,
+    );
+
+    fn setup(&mut self, res: &mut specs::Resources) {
+        use specs::prelude::SystemData;
+        Self::SystemData::setup(res);
+        self.reader = Some(
+            res.fetch_mut::<EventChannel<CollisionEvent>>()
+                .register_reader(),
+        );
+    }
+
+    fn run(&mut self, (events, mut game_events, mut player, mut stats): Self::SystemData) {
+        let reader = self.reader.as_mut().expect("ObstacleHitSystem wasn't set up");
+        for event in events.read(reader) {
+            let contact = match *event {
+                CollisionEvent::Started(ref contact) => contact,
+                CollisionEvent::Stopped(_) => continue,
+            };
+            for contact in &contact.both_ways() {
+                if let Some(player) = player.get_mut(contact.a) {
+                    if Layer::Obstacle.is_in(&contact.b_groups)
+                        && player.tumbling_timer <= 0.0
+                    {
+                        player.tumbling_timer = TUMBLE_TIME;
+                        stats.obstacles_hit += 1;
+                        game_events.single_write(GameEvent::ObstacleHit);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Moves everything with a `Motion` except the player, who gets
+/// `Simulation::run_player_motion()` instead.  Acceleration gets used
+/// up and reset every tick, so things like `GravitySystem` need to
+/// run first.
 pub struct NCollideMotionSystem {}
 
 impl<'a> specs::System<'a> for NCollideMotionSystem {
     type SystemData = (
-        specs::WriteStorage<'a, Collider>,
+        specs::Read<'a, DeltaTime>,
+        specs::Read<'a, Integrator>,
+        specs::ReadStorage<'a, Player>,
+        specs::ReadStorage<'a, Collider>,
         specs::WriteStorage<'a, Motion>,
         // Gotta use the panic handler here 'cause there is no default
         // we can provide for CollisionWorld, I guess.
         specs::Write<'a, CollisionWorld, specs::shred::PanicHandler>,
     );
 
-    fn run(&mut self, (mut collider, mut motion, mut ncollide_world): Self::SystemData) {
-        for (collider, motion) in (&mut collider, &mut motion).join() {
-            motion.velocity += motion.acceleration;
+    fn run(
+        &mut self,
+        (dt, integrator, player, collider, mut motion, mut ncollide_world): Self::SystemData,
+    ) {
+        let dt = dt.0;
+        for (collider, motion, _) in (&collider, &mut motion, !&player).join() {
+            let acceleration = motion.acceleration;
+            let displacement = match *integrator {
+                Integrator::SemiImplicitEuler => {
+                    motion.velocity += acceleration * dt;
+                    motion.velocity * dt
+                }
+                Integrator::VelocityVerlet => {
+                    // Finish last tick's velocity step now that we know the
+                    // new acceleration, then do this tick's position.
+                    if let Some(last) = motion.last_acceleration {
+                        motion.velocity += (last + acceleration) * (0.5 * dt);
+                    }
+                    motion.velocity * dt + acceleration * (0.5 * dt * dt)
+                }
+            };
+            motion.last_acceleration = Some(acceleration);
             motion.acceleration = na::zero();
 
             let new_position = {
-                let collision_obj = ncollide_world
-                    .collision_object(collider.object_handle)
-                    .expect(
-                        "Invalid collision object; was it removed from ncollide but not specs?",
-                    );
+                let collision_obj = match ncollide_world.collision_object(collider.object_handle)
+                {
+                    Some(obj) => obj,
+                    None => continue,
+                };
                 let mut new_position = collision_obj.position().clone();
-                new_position.append_translation_mut(&na::Translation::from_vector(motion.velocity));
+                new_position.append_translation_mut(&na::Translation::from_vector(displacement));
                 new_position
             };
             ncollide_world.set_position(collider.object_handle, new_position);
@@ -114,6 +357,52 @@ impl<'a> specs::System<'a> for NCollideMotionSystem {
     }
 }
 
+/// Keeps specs and ncollide in sync when something has been deleted
+/// from only one of them.  Entities whose collision object is gone
+/// get deleted, and collision objects whose entity is gone (or no
+/// longer points at them) get removed.
+///
+/// Deleting through `World::despawn` shouldn't leave any of these
+/// around, this is for everything else.  Run it after `maintain()`,
+/// since entities deleted through `Entities` are still alive until then.
+pub struct ColliderCleanupSystem {}
+
+impl<'a> specs::System<'a> for ColliderCleanupSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        specs::ReadStorage<'a, Collider>,
+        specs::Write<'a, CollisionWorld, specs::shred::PanicHandler>,
+    );
+
+    fn run(&mut self, (entities, collider, mut ncollide_world): Self::SystemData) {
+        for (entity, collider) in (&*entities, &collider).join() {
+            if ncollide_world
+                .collision_object(collider.object_handle)
+                .is_none()
+            {
+                debug!("Entity {:?} lost its collision object, deleting it", entity);
+                if let Err(e) = entities.delete(entity) {
+                    warn!("Could not delete entity {:?}: {:?}", entity, e);
+                }
+            }
+        }
+
+        let orphans: Vec<_> = ncollide_world
+            .collision_objects()
+            .filter(|obj| {
+                let entity = *obj.data();
+                !entities.is_alive(entity)
+                    || collider.get(entity).map(|c| c.object_handle) != Some(obj.handle())
+            })
+            .map(|obj| obj.handle())
+            .collect();
+        if !orphans.is_empty() {
+            debug!("Removing {} orphaned collision objects", orphans.len());
+            ncollide_world.remove(&orphans);
+        }
+    }
+}
+
 /*
 #[allow(dead_code)]
 pub struct PlayerMotionSystem {}
@@ -180,3 +469,86 @@ impl<'a> specs::System<'a> for DebugPrinterSystem {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::{Builder, RunNow};
+    use std::path;
+    use world::World;
+
+    fn player() -> Player {
+        Player {
+            on_ground: false,
+            jumping: false,
+            jump_force: 0.0,
+            velocity: 0.0,
+            run_acceleration: 0.0,
+            run_force: 0.0,
+            tumbling_timer: 0.0,
+            friction: 0.0,
+            control_mode: ControlMode::AutoRun,
+            forward: false,
+            back: false,
+            fast_falling: false,
+        }
+    }
+
+    fn contact(a: specs::Entity, a_layer: Layer, b: specs::Entity, b_layer: Layer) -> Contact {
+        Contact {
+            a,
+            b,
+            a_groups: a_layer.collision_groups(),
+            b_groups: b_layer.collision_groups(),
+        }
+    }
+
+    #[test]
+    fn leaving_one_planet_while_on_another_stays_grounded() {
+        let resource_dir = path::Path::new(env!("CARGO_MANIFEST_DIR")).join("resources");
+        let mut world = World::with_resource_dir(resource_dir);
+        let specs_world = &mut world.specs_world;
+        let player_entity = specs_world.create_entity().with(player()).build();
+        let planet = specs_world.create_entity().build();
+        let moon = specs_world.create_entity().build();
+
+        let mut grounding = GroundingSystem::default();
+        specs::System::setup(&mut grounding, &mut specs_world.res);
+        let mut landed = specs_world
+            .write_resource::<EventChannel<GameEvent>>()
+            .register_reader();
+
+        let mut send = |event: CollisionEvent| {
+            specs_world
+                .write_resource::<EventChannel<CollisionEvent>>()
+                .single_write(event);
+            grounding.run_now(&specs_world.res);
+            let on_ground = specs_world
+                .read_storage::<Player>()
+                .get(player_entity)
+                .unwrap()
+                .on_ground;
+            let events: Vec<GameEvent> = specs_world
+                .read_resource::<EventChannel<GameEvent>>()
+                .read(&mut landed)
+                .cloned()
+                .collect();
+            (on_ground, events)
+        };
+
+        let on_planet = contact(player_entity, Layer::Player, planet, Layer::Planet);
+        // The moon reports it the other way round.
+        let on_moon = contact(moon, Layer::Planet, player_entity, Layer::Player);
+        assert_eq!(
+            send(CollisionEvent::Started(on_planet)),
+            (true, vec![GameEvent::Landed])
+        );
+        assert_eq!(send(CollisionEvent::Started(on_moon)), (true, vec![]));
+        assert_eq!(send(CollisionEvent::Stopped(on_planet)), (true, vec![]));
+        assert_eq!(send(CollisionEvent::Stopped(on_moon)), (false, vec![]));
+        assert_eq!(
+            send(CollisionEvent::Started(on_moon)),
+            (true, vec![GameEvent::Landed])
+        );
+    }
+}
@@ -6,9 +6,69 @@
 // which means using a different version than is in ggez 0.4.3
 use nalgebra as na;
 use ncollide2d as nc;
+use rand::prng::XorShiftRng;
+use rand::SeedableRng;
+use ron;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use specs;
 
+use std::fs;
+use std::io::Write;
+use std::path;
+
+use error::Err;
+
 pub type Point2 = na::Point2<f32>;
 pub type Vector2 = na::Vector2<f32>;
 pub type CollisionWorld = nc::world::CollisionWorld<f32, specs::Entity>;
 pub type CollisionObject = nc::world::CollisionObject<f32, specs::Entity>;
+
+/// Makes a RNG that always gives the same numbers for the same seed,
+/// on every platform.
+pub fn rng_from_seed(seed: u64) -> XorShiftRng {
+    let mut bytes = [0u8; 16];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        // XorShift hates all-zero seeds, so mix in the index too.
+        *byte = ((seed >> ((i % 8) * 8)) as u8) ^ (i as u8);
+    }
+    XorShiftRng::from_seed(bytes)
+}
+
+/// Turns something like a level name into something we can
+/// safely stick in a file name.
+pub fn file_safe_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Loads something we saved with `save_ron()`.
+pub fn load_ron<T: DeserializeOwned>(path: &path::Path) -> Result<T, Err> {
+    let file = fs::File::open(path)?;
+    let value = ron::de::from_reader(file)?;
+    Ok(value)
+}
+
+/// Saves something as RON, pretty so it's easy to poke at by hand,
+/// making the directory it goes in if need be.
+pub fn save_ron<T: Serialize>(path: &path::Path, value: &T) -> Result<(), Err> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let s = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())?;
+    let mut file = fs::File::create(path)?;
+    file.write_all(s.as_bytes())?;
+    Ok(())
+}
+
+/// Makes a fresh, empty directory for a test to save things in.
+#[cfg(test)]
+pub fn test_dir(name: &str) -> path::PathBuf {
+    use std::{env, process};
+
+    let dir = env::temp_dir().join(format!("running_into_space-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("Could not make test directory");
+    dir
+}
@@ -6,33 +6,202 @@
 use ggez;
 use ggez_goodies::input as ginput;
 use ncollide2d as nc;
-use specs;
+use rand;
+use rand::prng::XorShiftRng;
+use specs::{self, RunNow};
 
 use warmy;
 
 use std::path;
 
+use audio;
+use campaign::Campaign;
 use components::*;
 use input;
+use scores::{HighScores, NewBests};
+use settings::Settings;
+use systems::ColliderCleanupSystem;
 use util::*;
 
+/// How many times per second we run the simulation.
+pub const DESIRED_FPS: u32 = 60;
+
+/// specs resource saying how long one simulation tick is, in seconds.
+/// Anything that changes over time should scale by this rather than
+/// assuming a tick rate.
+#[derive(Copy, Clone, Debug)]
+pub struct DeltaTime(pub f32);
+
+impl Default for DeltaTime {
+    fn default() -> Self {
+        DeltaTime(1.0 / DESIRED_FPS as f32)
+    }
+}
+
+/// specs resource saying how `NCollideMotionSystem` integrates motion.
+/// Set per level, see `LevelDef::integrator`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Integrator {
+    /// Velocity first, then position.  Cheap and good enough for
+    /// most things.
+    SemiImplicitEuler,
+    /// Better at keeping orbits from slowly drifting, at the cost
+    /// of remembering last tick's acceleration.
+    VelocityVerlet,
+}
+
+impl Default for Integrator {
+    fn default() -> Self {
+        Integrator::SemiImplicitEuler
+    }
+}
+
+/// specs resource with stats about the current run, so we can show
+/// them off at the end.
+#[derive(Clone, Debug, Default)]
+pub struct RunStats {
+    /// Seconds since the level started.
+    pub time: f32,
+    /// Fastest the player has gone, in pixels/s.
+    pub peak_velocity: f32,
+    pub obstacles_hit: u32,
+}
+
+/// Something a scene pushed over a `LevelScene` wants it to do once
+/// it's back on top.  Whatever it was leaves one of these in
+/// `World::level_command` as it goes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LevelCommand {
+    Restart,
+    QuitToTitle,
+    QuitGame,
+}
+
+/// specs resource with the RNG for everything random in a level,
+/// so the same seed always gives the same level.
+pub struct GameRng {
+    pub rng: XorShiftRng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: rng_from_seed(seed),
+        }
+    }
+}
+
 pub struct World {
     pub assets: warmy::Store<ggez::Context>,
     pub input: input::InputState,
+    /// The player's preferences, including which keys they've picked.
+    pub settings: Settings,
+    /// The binding built from `settings.keys`.
+    pub input_binding: input::InputBinding,
+    /// While set, the next key pressed goes into `captured_key`
+    /// instead of being turned into an input event.
+    pub capturing_key: bool,
+    pub captured_key: Option<ggez::event::Keycode>,
+    pub audio: audio::Audio,
     pub specs_world: specs::World,
+    pub campaign: Campaign,
+    /// The seed every level gets built from.
+    pub seed: u64,
+    /// The player's best results on each level.
+    pub high_scores: HighScores,
+    /// Where to save replays and such, if anywhere.
+    pub save_dir: Option<path::PathBuf>,
+    /// Where to save settings, if anywhere.
+    pub config_dir: Option<path::PathBuf>,
+    /// Left for the `LevelScene` by whatever was pushed over it.
+    pub level_command: Option<LevelCommand>,
     pub quit: bool,
 }
 
 impl World {
+    /// Deletes an entity, along with its collision object if it has one.
+    /// Use this instead of deleting entities straight from specs, or
+    /// ncollide will end up full of objects that don't belong to anything.
+    pub fn despawn(&mut self, entity: specs::Entity) {
+        let handle = self
+            .specs_world
+            .read_storage::<Collider>()
+            .get(entity)
+            .map(|c| c.object_handle);
+        if let Some(handle) = handle {
+            self.specs_world
+                .write_resource::<CollisionWorld>()
+                .remove(&[handle]);
+        }
+        if let Err(e) = self.specs_world.delete_entity(entity) {
+            warn!("Could not delete entity {:?}: {:?}", entity, e);
+        }
+    }
+
+    /// Applies pending entity deletions, then cleans up anything
+    /// that got deleted from only one of specs or ncollide.
+    pub fn maintain(&mut self) {
+        self.specs_world.maintain();
+        ColliderCleanupSystem {}.run_now(&self.specs_world.res);
+        self.specs_world.maintain();
+    }
+
+    /// Makes the input binding and audio match `settings`.  The window
+    /// needs a `Context`; see `Settings::apply_window()`.
+    pub fn apply_settings(&mut self) {
+        self.input_binding = self.settings.keys.input_binding();
+        self.audio.set_volumes(self.settings.volumes);
+    }
+
+    /// Saves `settings`, if there's anywhere to put them.
+    pub fn save_settings(&self) {
+        if let Some(ref dir) = self.config_dir {
+            if let Err(e) = self.settings.save(dir) {
+                warn!("Could not save settings: {}", e);
+            }
+        }
+    }
+
+    /// Saves how far the player's got through the campaign, if
+    /// there's anywhere to put it.
+    pub fn save_progress(&self) {
+        if let Some(ref dir) = self.save_dir {
+            if let Err(e) = self.campaign.save_progress(dir) {
+                warn!("Could not save campaign progress: {}", e);
+            }
+        }
+    }
+
+    /// Records a finished run in `high_scores` and saves them, if
+    /// there's anywhere to put them.  Returns which stats were new bests.
+    pub fn record_run(&mut self, level: &str, stats: &RunStats) -> NewBests {
+        let new = self.high_scores.record(level, stats);
+        if let Some(ref dir) = self.save_dir {
+            if let Err(e) = self.high_scores.save(dir) {
+                warn!("Could not save high scores: {}", e);
+            }
+        }
+        new
+    }
+
+    /// Switches to a new set of key bindings, and saves them.
+    pub fn set_key_bindings(&mut self, bindings: input::KeyBindings) {
+        self.settings.keys = bindings;
+        self.apply_settings();
+        self.save_settings();
+    }
+
     fn register_components(&mut self) {
         self.specs_world.register::<Collider>();
         self.specs_world.register::<Motion>();
         self.specs_world.register::<Mass>();
         self.specs_world.register::<Player>();
         self.specs_world.register::<Sprite>();
+        self.specs_world.register::<Animation>();
         self.specs_world.register::<Mesh>();
         self.specs_world.register::<Gravity>();
         self.specs_world.register::<Obstacle>();
+        self.specs_world.register::<Ghost>();
     }
 
     pub fn new(ctx: &mut ggez::Context, resource_dir: Option<path::PathBuf>) -> Self {
@@ -46,6 +215,12 @@ impl World {
             Some(s) => s,
             None => ctx.filesystem.get_resources_dir().to_owned(),
         };
+        Self::with_resource_dir(resource_pathbuf)
+    }
+
+    /// Creates a `World` without needing a `ggez::Context`,
+    /// so we can run a `Simulation` headless.
+    pub fn with_resource_dir(resource_pathbuf: path::PathBuf) -> Self {
         info!("Setting up resource path: {:?}", resource_pathbuf);
         let opt = warmy::StoreOpt::default().set_root(resource_pathbuf);
         let store = warmy::Store::new(opt)
@@ -54,11 +229,28 @@ impl World {
         let mut w = specs::World::new();
         let collide_world: CollisionWorld = nc::world::CollisionWorld::new(0.02);
         w.add_resource(collide_world);
+        w.add_resource(DeltaTime::default());
+        w.add_resource(Integrator::default());
+        w.add_resource(RunStats::default());
+        let seed = rand::random::<u32>() as u64;
+        w.add_resource(GameRng::new(seed));
 
         let mut the_world = Self {
             assets: store,
             input: ginput::InputState::new(),
+            settings: Settings::default(),
+            input_binding: input::create_input_binding(),
+            capturing_key: false,
+            captured_key: None,
+            // Stays quiet unless whoever makes us hooks up real audio.
+            audio: audio::Audio::null(),
             specs_world: w,
+            campaign: Campaign::default(),
+            seed,
+            high_scores: HighScores::default(),
+            save_dir: None,
+            config_dir: None,
+            level_command: None,
             quit: false,
         };
 